@@ -0,0 +1,147 @@
+use crate::header::Header;
+use crate::opcodes::Mnemonic;
+use crate::zcode::ZInstruction;
+use crate::zversion::ZVersion;
+
+/// A packed address as found in operands and tables: the
+/// byte address of a routine or string divided by 2, 4 or 8
+/// depending on the version. In versions 6 and 7 routines
+/// and strings additionally are relative to the routines
+/// and strings offsets of the header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PackedAddress(pub u16);
+
+/// Factor packed addresses are multiplied by.
+fn scale(version: ZVersion) -> usize {
+    match version {
+        ZVersion::V1 | ZVersion::V2 | ZVersion::V3 => 2,
+        ZVersion::V4 | ZVersion::V5 | ZVersion::V6 | ZVersion::V7 => 4,
+        ZVersion::V8 => 8,
+    }
+}
+
+/// Byte offset added in versions 6 and 7 given the offset
+/// (divided by 8) stored in the header.
+fn offset(version: ZVersion, offset: u16) -> usize {
+    match version {
+        ZVersion::V6 | ZVersion::V7 => 8 * offset as usize,
+        _ => 0,
+    }
+}
+
+fn pack(address: usize, version: ZVersion, base: usize) -> Option<PackedAddress> {
+    let relative = address.checked_sub(base)?;
+    if relative % scale(version) != 0 {
+        return None;
+    }
+    Some(PackedAddress(u16::try_from(relative / scale(version)).ok()?))
+}
+
+impl PackedAddress {
+    /// Byte address of the routine this packed address refers
+    /// to. `routines_offset` is only used in versions 6 and 7.
+    pub fn unpack_routine(&self, version: ZVersion, routines_offset: u16) -> usize {
+        scale(version) * self.0 as usize + offset(version, routines_offset)
+    }
+
+    /// Byte address of the string this packed address refers
+    /// to. `strings_offset` is only used in versions 6 and 7.
+    pub fn unpack_string(&self, version: ZVersion, strings_offset: u16) -> usize {
+        scale(version) * self.0 as usize + offset(version, strings_offset)
+    }
+
+    /// Packed address of the routine at byte `address`. Fails
+    /// if the address is not properly aligned or out of reach.
+    pub fn pack_routine(address: usize, version: ZVersion, routines_offset: u16) -> Option<PackedAddress> {
+        pack(address, version, offset(version, routines_offset))
+    }
+
+    /// Packed address of the string at byte `address`. Fails
+    /// if the address is not properly aligned or out of reach.
+    pub fn pack_string(address: usize, version: ZVersion, strings_offset: u16) -> Option<PackedAddress> {
+        pack(address, version, offset(version, strings_offset))
+    }
+
+    /// Same as `unpack_routine` using version and offset of `header`.
+    pub fn routine(&self, header: &Header) -> usize {
+        self.unpack_routine(header.version(), header.routines_offset())
+    }
+
+    /// Same as `unpack_string` using version and offset of `header`.
+    pub fn string(&self, header: &Header) -> usize {
+        self.unpack_string(header.version(), header.strings_offset())
+    }
+}
+
+impl ZInstruction {
+    /// Byte address of the routine called by one of the call
+    /// instructions. Only known if the routine is given as a
+    /// constant; calls to routine 0 do nothing and yield `None`.
+    pub fn call_target(&self, header: &Header) -> Option<usize> {
+        if !self.info(header.version())?.mnemonic.is_call() {
+            return None;
+        }
+        match self.operands()[0]?.constant()? {
+            0 => None,
+            packed => Some(PackedAddress(packed).routine(header)),
+        }
+    }
+
+    /// Byte address of the string printed by `print_paddr`
+    /// if given as a constant.
+    pub fn paddr_string(&self, header: &Header) -> Option<usize> {
+        if self.info(header.version())?.mnemonic != Mnemonic::PrintPaddr {
+            return None;
+        }
+        Some(PackedAddress(self.operands()[0]?.constant()?).string(header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PackedAddress;
+    use crate::header::Header;
+    use crate::zcode::decode_instruction;
+    use crate::zversion::ZVersion;
+
+    #[test]
+    fn test_packed_address_scaling() {
+        let p = PackedAddress(0x1234);
+        assert_eq!(p.unpack_routine(ZVersion::V3, 0x100), 0x2468);
+        assert_eq!(p.unpack_string(ZVersion::V5, 0x100), 0x48d0);
+        assert_eq!(p.unpack_routine(ZVersion::V6, 0x100), 0x48d0 + 0x800);
+        assert_eq!(p.unpack_string(ZVersion::V7, 0x010), 0x48d0 + 0x80);
+        assert_eq!(p.unpack_routine(ZVersion::V8, 0x100), 0x91a0);
+
+        assert_eq!(PackedAddress::pack_routine(0x2468, ZVersion::V3, 0), Some(p));
+        assert_eq!(PackedAddress::pack_string(0x48d0 + 0x80, ZVersion::V7, 0x010), Some(p));
+        assert_eq!(PackedAddress::pack_routine(0x2466, ZVersion::V5, 0), None);
+        assert_eq!(PackedAddress::pack_routine(0x10, ZVersion::V6, 0x10), None);
+        assert_eq!(PackedAddress::pack_routine(0x20000, ZVersion::V3, 0), None);
+    }
+
+    #[test]
+    fn test_instruction_resolves_packed_addresses() {
+        let mut memory = vec![0u8; 0x100];
+        memory[0] = 5;
+        memory[0x0f] = 0x40;
+        let header = Header::parse(&memory).unwrap();
+
+        // call_vs 0x0020 -> sp
+        memory[0x40..0x45].copy_from_slice(&[0xe0, 0x3f, 0x00, 0x20, 0x00]);
+        let instruction = decode_instruction(&memory, 0x40, ZVersion::V5).unwrap();
+        assert_eq!(instruction.call_target(&header), Some(0x80));
+        assert_eq!(instruction.paddr_string(&header), None);
+
+        // call_1n L01 - target unknown
+        memory[0x40..0x42].copy_from_slice(&[0xaf, 0x01]);
+        let instruction = decode_instruction(&memory, 0x40, ZVersion::V5).unwrap();
+        assert_eq!(instruction.call_target(&header), None);
+
+        // print_paddr 0x0030
+        memory[0x40..0x43].copy_from_slice(&[0x8d, 0x00, 0x30]);
+        let instruction = decode_instruction(&memory, 0x40, ZVersion::V5).unwrap();
+        assert_eq!(instruction.paddr_string(&header), Some(0xc0));
+        assert_eq!(instruction.call_target(&header), None);
+    }
+}
@@ -0,0 +1,187 @@
+use std::cmp::Ordering;
+
+use crate::header::Header;
+use crate::util::{read_word, ZCodec};
+use crate::zversion::ZVersion;
+
+/// A story's dictionary: the list of word separators followed
+/// by the entries, each starting with the encoded word (4
+/// bytes in versions 1-3, 6 bytes since version 4) and
+/// followed by game specific data up to the entry length.
+///
+/// Entries are normally sorted by their encoded words so they
+/// can be searched with a binary search. A negative entry
+/// count marks an unsorted dictionary (games may build such
+/// dictionaries for `tokenise`) which has to be searched
+/// linearly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dictionary {
+    pub version: ZVersion,
+    pub address: usize,
+    /// zscii codes of the word separators
+    pub separators: Vec<u8>,
+    pub entry_length: usize,
+    pub count: usize,
+    pub sorted: bool,
+    /// byte address of the first entry
+    pub entries: usize,
+}
+
+impl Dictionary {
+    /// Parse the dictionary header at byte `address`, checking
+    /// that all entries lie within `memory`.
+    pub fn parse(memory: &[u8], address: usize, version: ZVersion) -> Option<Dictionary> {
+        let n = *memory.get(address)? as usize;
+        let separators = memory.get(address + 1..address + 1 + n)?.to_vec();
+        let entry_length = *memory.get(address + 1 + n)? as usize;
+        let count = read_word(memory, address + 2 + n)? as i16;
+        let entries = address + 4 + n;
+
+        let dictionary = Dictionary {
+            version,
+            address,
+            separators,
+            entry_length,
+            count: count.unsigned_abs() as usize,
+            sorted: count >= 0,
+            entries,
+        };
+        if dictionary.count > 0 && entry_length < dictionary.word_length() {
+            return None;
+        }
+        if entries + entry_length * dictionary.count > memory.len() {
+            return None;
+        }
+        Some(dictionary)
+    }
+
+    /// The dictionary the header points to.
+    pub fn from_header(memory: &[u8], header: &Header) -> Option<Dictionary> {
+        Dictionary::parse(memory, header.dictionary() as usize, header.version())
+    }
+
+    /// Length in bytes of an encoded word.
+    pub fn word_length(&self) -> usize {
+        if self.version <= ZVersion::V3 { 4 } else { 6 }
+    }
+
+    pub fn is_separator(&self, zscii: u8) -> bool {
+        self.separators.contains(&zscii)
+    }
+
+    /// Byte address of entry `index` (counting from 0).
+    pub fn entry_address(&self, index: usize) -> Option<usize> {
+        if index >= self.count {
+            return None;
+        }
+        Some(self.entries + index * self.entry_length)
+    }
+
+    /// The encoded word of entry `index`.
+    pub fn encoded<'a>(&self, memory: &'a [u8], index: usize) -> Option<&'a [u8]> {
+        let address = self.entry_address(index)?;
+        memory.get(address..address + self.word_length())
+    }
+
+    /// The word of entry `index` as text.
+    pub fn word(&self, memory: &[u8], index: usize, codec: &ZCodec) -> Option<String> {
+        Some(codec.decode(self.encoded(memory, index)?.to_vec(), None))
+    }
+
+    /// All words of the dictionary, in the order stored.
+    pub fn words(&self, memory: &[u8], codec: &ZCodec) -> Vec<String> {
+        (0..self.count).filter_map(|i| self.word(memory, i, codec)).collect()
+    }
+
+    /// Index of the entry holding the encoded word `word`
+    /// (as produced by `ZCodec::encode_dictionary_word`).
+    pub fn find(&self, memory: &[u8], word: &[u8]) -> Option<usize> {
+        if word.len() != self.word_length() {
+            return None;
+        }
+        if !self.sorted {
+            return (0..self.count).find(|&i| self.encoded(memory, i) == Some(word));
+        }
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.encoded(memory, middle)?.cmp(word) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Some(middle),
+            }
+        }
+        None
+    }
+
+    /// Byte address of the entry for `word`, which is what
+    /// `read` stores in the parse buffer.
+    pub fn lookup(&self, memory: &[u8], word: &str, codec: &ZCodec) -> Option<usize> {
+        let index = self.find(memory, &codec.encode_dictionary_word(word))?;
+        self.entry_address(index)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Dictionary;
+    use crate::util::ZCodec;
+    use crate::zversion::ZVersion;
+
+    /// Dictionary at 0x10 with separators '.' and ',' and the
+    /// given words in the given order, each followed by `data`.
+    pub fn story(version: ZVersion, words: &[&str], data: &[u8], count: i16) -> Vec<u8> {
+        let codec = ZCodec::new(version);
+        let mut memory = vec![0u8; 0x10];
+        let length = codec.encode_dictionary_word("").len() + data.len();
+        memory.extend_from_slice(&[2, b'.', b',', length as u8]);
+        memory.extend_from_slice(&count.to_be_bytes());
+        for word in words {
+            memory.extend_from_slice(&codec.encode_dictionary_word(word));
+            memory.extend_from_slice(data);
+        }
+        memory
+    }
+
+    #[test]
+    fn test_dictionary_parses_and_lists_words() {
+        let memory = story(ZVersion::V3, &["lamp", "north", "take"], &[0x80, 0x01, 0x00], 3);
+        let codec = ZCodec::new(ZVersion::V3);
+        let dictionary = Dictionary::parse(&memory, 0x10, ZVersion::V3).unwrap();
+
+        assert_eq!(dictionary.separators, vec![b'.', b',']);
+        assert!(dictionary.is_separator(b','));
+        assert!(!dictionary.is_separator(b' '));
+        assert_eq!((dictionary.entry_length, dictionary.count, dictionary.sorted), (7, 3, true));
+        assert_eq!(dictionary.entries, 0x16);
+        assert_eq!(dictionary.words(&memory, &codec), vec!["lamp", "north", "take"]);
+        assert_eq!(Dictionary::parse(&memory[..0x20], 0x10, ZVersion::V3), None);
+    }
+
+    #[test]
+    fn test_dictionary_lookup() {
+        let codec = ZCodec::new(ZVersion::V3);
+        let words = ["lamp", "lantern", "take", "zork"];
+        let mut sorted: Vec<Vec<u8>> = words.iter().map(|w| codec.encode_dictionary_word(w)).collect();
+        sorted.sort();
+        let sorted: Vec<String> = sorted.into_iter().map(|z| codec.decode(z, None)).collect();
+        let sorted: Vec<&str> = sorted.iter().map(String::as_str).collect();
+
+        let memory = story(ZVersion::V3, &sorted, &[0x80, 0x01, 0x00], 4);
+        let dictionary = Dictionary::parse(&memory, 0x10, ZVersion::V3).unwrap();
+        for (i, word) in sorted.iter().enumerate() {
+            assert_eq!(dictionary.lookup(&memory, word, &codec), Some(0x16 + 7 * i));
+        }
+        // only the first six z-chars count
+        assert_eq!(dictionary.lookup(&memory, "lanterns", &codec), dictionary.lookup(&memory, "lantern", &codec));
+        assert!(dictionary.lookup(&memory, "lanterns", &codec).is_some());
+        assert_eq!(dictionary.lookup(&memory, "south", &codec), None);
+
+        let memory = story(ZVersion::V3, &["zork", "lamp", "take"], &[0x80, 0x01, 0x00], -3);
+        let dictionary = Dictionary::parse(&memory, 0x10, ZVersion::V3).unwrap();
+        assert!(!dictionary.sorted);
+        assert_eq!(dictionary.lookup(&memory, "lamp", &codec), Some(0x16 + 7));
+        assert_eq!(dictionary.lookup(&memory, "zork", &codec), Some(0x16));
+        assert_eq!(dictionary.lookup(&memory, "north", &codec), None);
+    }
+}
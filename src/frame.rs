@@ -0,0 +1,119 @@
+use crate::util::read_word;
+use crate::zversion::ZVersion;
+
+/// The header of a routine: the number of locals (at most
+/// 15) and, in versions 1-4, their initial values. Later
+/// versions initialise locals to 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutineHeader {
+    pub address: usize,
+    pub locals: Vec<u16>,
+    /// byte address of the first instruction
+    pub code: usize,
+}
+
+impl RoutineHeader {
+    pub fn parse(memory: &[u8], address: usize, version: ZVersion) -> Option<RoutineHeader> {
+        let count = *memory.get(address)? as usize;
+        if count > 15 {
+            return None;
+        }
+        if version >= ZVersion::V5 {
+            return Some(RoutineHeader { address, locals: vec![0; count], code: address + 1 });
+        }
+        let locals = (0..count)
+            .map(|n| read_word(memory, address + 1 + 2 * n))
+            .collect::<Option<Vec<u16>>>()?;
+        Some(RoutineHeader { address, locals, code: address + 1 + 2 * count })
+    }
+}
+
+/// What happens to the value a routine returns.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameResult {
+    /// stored to a variable (`call_vs`, `call_1s`, ...)
+    Store(u8),
+    /// thrown away (`call_vn`, `call_1n`, ...)
+    Discard,
+    /// handed back to the interpreter which called the routine
+    /// on its own, e.g. for timed input or sound effects
+    Interrupt,
+}
+
+/// A routine call in progress.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// where to continue after the routine returns
+    pub return_pc: usize,
+    pub locals: Vec<u16>,
+    /// height of the stack when the routine was called, the
+    /// routine's part of the stack lies above
+    pub stack_base: usize,
+    pub result: FrameResult,
+    /// number of arguments supplied
+    pub arg_count: usize,
+}
+
+impl Frame {
+    /// Frame for a call of `routine` with `args`. Arguments
+    /// beyond the routine's locals are dropped.
+    pub fn new(
+        routine: &RoutineHeader,
+        args: &[u16],
+        return_pc: usize,
+        stack_base: usize,
+        result: FrameResult
+    ) -> Frame {
+        let mut locals = routine.locals.clone();
+        for (local, arg) in locals.iter_mut().zip(args) {
+            *local = *arg;
+        }
+        Frame { return_pc, locals, stack_base, result, arg_count: args.len() }
+    }
+
+    /// Frame of the top level of a story, which has neither
+    /// locals nor a caller to return to.
+    pub fn top() -> Frame {
+        Frame { return_pc: 0, locals: Vec::new(), stack_base: 0, result: FrameResult::Discard, arg_count: 0 }
+    }
+
+    /// Whether argument `n` (counting from 1) was supplied,
+    /// as tested by `check_arg_count`.
+    pub fn has_arg(&self, n: u16) -> bool {
+        n as usize <= self.arg_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frame, FrameResult, RoutineHeader};
+    use crate::zversion::ZVersion;
+
+    #[test]
+    fn test_routine_header_parses_locals() {
+        let memory = [0x00, 0x03, 0x00, 0x01, 0x00, 0x02, 0xff, 0xff, 0xb0];
+        let routine = RoutineHeader::parse(&memory, 1, ZVersion::V3).unwrap();
+        assert_eq!(routine.locals, vec![1, 2, 0xffff]);
+        assert_eq!(routine.code, 8);
+
+        let routine = RoutineHeader::parse(&memory, 1, ZVersion::V5).unwrap();
+        assert_eq!(routine.locals, vec![0, 0, 0]);
+        assert_eq!(routine.code, 2);
+
+        assert_eq!(RoutineHeader::parse(&memory, 6, ZVersion::V5), None);
+        assert_eq!(RoutineHeader::parse(&memory[..6], 1, ZVersion::V3), None);
+    }
+
+    #[test]
+    fn test_frame_copies_arguments() {
+        let routine = RoutineHeader { address: 0x400, locals: vec![1, 2, 3], code: 0x407 };
+        let frame = Frame::new(&routine, &[7, 8], 0x123, 4, FrameResult::Store(16));
+        assert_eq!(frame.locals, vec![7, 8, 3]);
+        assert!(frame.has_arg(2));
+        assert!(!frame.has_arg(3));
+
+        let frame = Frame::new(&routine, &[9, 9, 9, 9], 0x123, 4, FrameResult::Discard);
+        assert_eq!(frame.locals, vec![9, 9, 9]);
+        assert_eq!(frame.arg_count, 4);
+    }
+}
@@ -0,0 +1,508 @@
+use std::fmt;
+
+use crate::util::read_word;
+use crate::zversion::ZVersion;
+
+/// The 64 byte header found at the start of every story
+/// file. Accessors read the fields straight from a copy of
+/// the header bytes, fields not used by the story's version
+/// simply read as whatever the story file contains there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    bytes: [u8; 64],
+    version: ZVersion,
+}
+
+/// Reasons for a story file to be rejected by `Header::parse`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderError {
+    /// the story file is shorter than the header
+    TooShort { length: usize },
+
+    /// the version byte is not in 1..=8
+    UnsupportedVersion { version: u8 },
+
+    /// a header field points outside the story file
+    AddressOutOfRange { field: &'static str, address: usize },
+
+    /// static memory starts inside the header
+    StaticMemoryTooLow { address: usize },
+
+    /// the story file is shorter than the header claims
+    Truncated { expected: usize, length: usize },
+
+    /// the file length in the header ends inside the header
+    FileLengthTooShort { length: usize },
+
+    /// the story file is too long for its version
+    TooLong { length: usize },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::TooShort { length } => {
+                write!(f, "story file of {} bytes is too short for a header", length)
+            },
+            HeaderError::UnsupportedVersion { version } => {
+                write!(f, "unsupported version {}", version)
+            },
+            HeaderError::AddressOutOfRange { field, address } => {
+                write!(f, "{} at {:#06x} lies outside the story file", field, address)
+            },
+            HeaderError::StaticMemoryTooLow { address } => {
+                write!(f, "static memory at {:#06x} overlaps the header", address)
+            },
+            HeaderError::Truncated { expected, length } => {
+                write!(f, "story file has {} bytes but should have {}", length, expected)
+            },
+            HeaderError::FileLengthTooShort { length } => {
+                write!(f, "file length {} given in the header is too short for a header", length)
+            },
+            HeaderError::TooLong { length } => {
+                write!(f, "story file of {} bytes is too long for its version", length)
+            },
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+impl Header {
+    /// Parse and validate the header of the story file
+    /// `memory`: the version must be known and the table
+    /// addresses must lie within the story file.
+    pub fn parse(memory: &[u8]) -> Result<Header, HeaderError> {
+        if memory.len() < 64 {
+            return Err(HeaderError::TooShort { length: memory.len() });
+        }
+        let version = ZVersion::from_u8(memory[0])
+            .ok_or(HeaderError::UnsupportedVersion { version: memory[0] })?;
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(&memory[..64]);
+        let header = Header { bytes, version };
+
+        let length = header.file_length();
+        if length > memory.len() {
+            return Err(HeaderError::Truncated { expected: length, length: memory.len() });
+        }
+        if length != 0 && length < 64 {
+            return Err(HeaderError::FileLengthTooShort { length });
+        }
+        let static_base = header.static_memory_base() as usize;
+        if static_base < 64 {
+            return Err(HeaderError::StaticMemoryTooLow { address: static_base });
+        }
+
+        let mut fields = vec![
+            ("static memory base", static_base),
+            ("high memory base", header.high_memory_base() as usize),
+            ("dictionary", header.dictionary() as usize),
+            ("object table", header.object_table() as usize),
+            ("global variables", header.globals() as usize),
+        ];
+        if version >= ZVersion::V2 {
+            fields.push(("abbreviations table", header.abbreviations() as usize));
+        }
+        if version >= ZVersion::V5 {
+            fields.push(("terminating characters table", header.terminating_chars() as usize));
+            fields.push(("alphabet table", header.alphabet_table() as usize));
+            fields.push(("header extension table", header.extension_table() as usize));
+        }
+        for (field, address) in fields {
+            if address > memory.len() {
+                return Err(HeaderError::AddressOutOfRange { field, address });
+            }
+        }
+        Ok(header)
+    }
+
+    fn byte(&self, offset: usize) -> u8 {
+        self.bytes[offset]
+    }
+
+    fn word(&self, offset: usize) -> u16 {
+        read_word(&self.bytes, offset).unwrap_or(0)
+    }
+
+    pub fn version(&self) -> ZVersion {
+        self.version
+    }
+
+    pub fn flags1(&self) -> u8 {
+        self.byte(0x01)
+    }
+
+    pub fn release(&self) -> u16 {
+        self.word(0x02)
+    }
+
+    pub fn high_memory_base(&self) -> u16 {
+        self.word(0x04)
+    }
+
+    /// Address of the first instruction to execute (all
+    /// versions but 6).
+    pub fn initial_pc(&self) -> u16 {
+        self.word(0x06)
+    }
+
+    /// Packed address of the main routine (version 6 only).
+    pub fn main_routine(&self) -> u16 {
+        self.word(0x06)
+    }
+
+    pub fn dictionary(&self) -> u16 {
+        self.word(0x08)
+    }
+
+    pub fn object_table(&self) -> u16 {
+        self.word(0x0a)
+    }
+
+    pub fn globals(&self) -> u16 {
+        self.word(0x0c)
+    }
+
+    pub fn static_memory_base(&self) -> u16 {
+        self.word(0x0e)
+    }
+
+    pub fn flags2(&self) -> u16 {
+        self.word(0x10)
+    }
+
+    /// Serial code, usually the compilation date as YYMMDD.
+    pub fn serial(&self) -> [u8; 6] {
+        let mut serial = [0u8; 6];
+        serial.copy_from_slice(&self.bytes[0x12..0x18]);
+        serial
+    }
+
+    pub fn abbreviations(&self) -> u16 {
+        self.word(0x18)
+    }
+
+    /// Length of the story file in bytes. The header stores
+    /// it divided by 2 (versions 1-3), 4 (versions 4-5) or
+    /// 8 (versions 6-8). Some early story files store 0.
+    pub fn file_length(&self) -> usize {
+        self.word(0x1a) as usize * self.length_scale()
+    }
+
+    /// Factor the file length is divided by in the header.
+    pub fn length_scale(&self) -> usize {
+        match self.version {
+            ZVersion::V1 | ZVersion::V2 | ZVersion::V3 => 2,
+            ZVersion::V4 | ZVersion::V5 => 4,
+            _ => 8,
+        }
+    }
+
+    pub fn checksum(&self) -> u16 {
+        self.word(0x1c)
+    }
+
+    pub fn interpreter_number(&self) -> u8 {
+        self.byte(0x1e)
+    }
+
+    pub fn interpreter_version(&self) -> u8 {
+        self.byte(0x1f)
+    }
+
+    /// Screen height in lines (255 means infinite).
+    pub fn screen_height_lines(&self) -> u8 {
+        self.byte(0x20)
+    }
+
+    /// Screen width in characters.
+    pub fn screen_width_chars(&self) -> u8 {
+        self.byte(0x21)
+    }
+
+    pub fn screen_width_units(&self) -> u16 {
+        self.word(0x22)
+    }
+
+    pub fn screen_height_units(&self) -> u16 {
+        self.word(0x24)
+    }
+
+    /// Font width in units (font height in version 6).
+    pub fn font_width(&self) -> u8 {
+        self.byte(0x26)
+    }
+
+    /// Font height in units (font width in version 6).
+    pub fn font_height(&self) -> u8 {
+        self.byte(0x27)
+    }
+
+    /// Routines offset divided by 8 (versions 6 and 7).
+    pub fn routines_offset(&self) -> u16 {
+        self.word(0x28)
+    }
+
+    /// Strings offset divided by 8 (versions 6 and 7).
+    pub fn strings_offset(&self) -> u16 {
+        self.word(0x2a)
+    }
+
+    pub fn default_background(&self) -> u8 {
+        self.byte(0x2c)
+    }
+
+    pub fn default_foreground(&self) -> u8 {
+        self.byte(0x2d)
+    }
+
+    pub fn terminating_chars(&self) -> u16 {
+        self.word(0x2e)
+    }
+
+    /// Total width in pixels of text sent to output stream 3.
+    pub fn stream3_width(&self) -> u16 {
+        self.word(0x30)
+    }
+
+    /// Revision of the standard the interpreter complies
+    /// to, as (major, minor).
+    pub fn standard_revision(&self) -> (u8, u8) {
+        (self.byte(0x32), self.byte(0x33))
+    }
+
+    pub fn alphabet_table(&self) -> u16 {
+        self.word(0x34)
+    }
+
+    pub fn extension_table(&self) -> u16 {
+        self.word(0x36)
+    }
+
+    /// Words of the header extension table (without the
+    /// leading word holding their number), empty if the
+    /// story does not have one.
+    pub fn extension(&self, memory: &[u8]) -> Option<Vec<u16>> {
+        let address = self.extension_table() as usize;
+        if self.version < ZVersion::V5 || address == 0 {
+            return Some(Vec::new());
+        }
+        let count = read_word(memory, address)? as usize;
+        (1..=count).map(|n| read_word(memory, address + 2 * n)).collect()
+    }
+}
+
+/// Outcome of `verify`: the checksum stored in the header
+/// and the one computed over the story file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChecksumReport {
+    /// file length (in bytes) the checksum covers
+    pub file_length: usize,
+    /// checksum word found in the header
+    pub expected: u16,
+    /// sum of the bytes from 0x40 up to the file length
+    pub computed: u16,
+}
+
+impl ChecksumReport {
+    pub fn is_valid(&self) -> bool {
+        self.expected == self.computed
+    }
+}
+
+/// Length the checksum is computed over: the file length
+/// from the header or, for old story files storing 0, the
+/// whole story file.
+fn checksum_length(header: &Header, memory: &[u8]) -> usize {
+    match header.file_length() {
+        0 => memory.len(),
+        length => length,
+    }
+}
+
+/// Compute the checksum of the story file `memory`: the
+/// sum (modulo 0x10000) of all bytes from 0x40 up to the
+/// file length given in the header.
+pub fn compute_checksum(memory: &[u8]) -> Result<u16, HeaderError> {
+    let header = Header::parse(memory)?;
+    let length = checksum_length(&header, memory);
+    let sum = memory[0x40..length]
+        .iter()
+        .fold(0u16, |sum, &b| sum.wrapping_add(b as u16));
+    Ok(sum)
+}
+
+/// Compare the checksum stored in the header with the one
+/// computed over the story file, as the `verify` opcode does.
+pub fn verify(memory: &[u8]) -> Result<ChecksumReport, HeaderError> {
+    let header = Header::parse(memory)?;
+    Ok(ChecksumReport {
+        file_length: checksum_length(&header, memory),
+        expected: header.checksum(),
+        computed: compute_checksum(memory)?,
+    })
+}
+
+/// Recompute the checksum of a (patched) story file and
+/// write it to the header. Returns the new checksum.
+pub fn write_checksum(memory: &mut [u8]) -> Result<u16, HeaderError> {
+    let checksum = compute_checksum(memory)?;
+    memory[0x1c..0x1e].copy_from_slice(&checksum.to_be_bytes());
+    Ok(checksum)
+}
+
+/// Set the file length in the header to the length of
+/// `memory`, padding the story file with zeros to a multiple
+/// of the version's length scale first. Returns the new
+/// file length in bytes.
+pub fn write_file_length(memory: &mut Vec<u8>) -> Result<usize, HeaderError> {
+    let scale = Header::parse(memory)?.length_scale();
+    let length = memory.len().div_ceil(scale) * scale;
+    let scaled = u16::try_from(length / scale)
+        .map_err(|_| HeaderError::TooLong { length })?;
+    memory.resize(length, 0);
+    memory[0x1a..0x1c].copy_from_slice(&scaled.to_be_bytes());
+    Ok(length)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{compute_checksum, verify, write_checksum, write_file_length, Header, HeaderError};
+    use crate::zversion::ZVersion;
+
+    /// A story file of 0x400 bytes whose header puts
+    /// abbreviations at 0x40, objects at 0x80, globals at
+    /// 0x100, the dictionary and static memory at 0x200 and
+    /// the initial pc at 0x300. The file length covers the
+    /// first 0x200 bytes only.
+    pub fn story(version: u8) -> Vec<u8> {
+        let mut memory = vec![0u8; 0x400];
+        memory[0x00] = version;
+        memory[0x01] = 0x10;
+        memory[0x02..0x04].copy_from_slice(&[0x00, 0x58]);
+        memory[0x04..0x06].copy_from_slice(&[0x03, 0x00]);
+        memory[0x06..0x08].copy_from_slice(&[0x03, 0x00]);
+        memory[0x08..0x0a].copy_from_slice(&[0x02, 0x00]);
+        memory[0x0a..0x0c].copy_from_slice(&[0x00, 0x80]);
+        memory[0x0c..0x0e].copy_from_slice(&[0x01, 0x00]);
+        memory[0x0e..0x10].copy_from_slice(&[0x02, 0x00]);
+        memory[0x12..0x18].copy_from_slice(b"840726");
+        memory[0x18..0x1a].copy_from_slice(&[0x00, 0x40]);
+        memory[0x1a..0x1c].copy_from_slice(&[0x01, 0x00]);
+        memory[0x1c..0x1e].copy_from_slice(&[0xa1, 0x29]);
+        memory
+    }
+
+    #[test]
+    fn test_header_parses_fields() {
+        let memory = story(3);
+        let header = Header::parse(&memory).unwrap();
+        assert_eq!(header.version(), ZVersion::V3);
+        assert_eq!(header.flags1(), 0x10);
+        assert_eq!(header.release(), 88);
+        assert_eq!(header.high_memory_base(), 0x300);
+        assert_eq!(header.initial_pc(), 0x300);
+        assert_eq!(header.dictionary(), 0x200);
+        assert_eq!(header.object_table(), 0x80);
+        assert_eq!(header.globals(), 0x100);
+        assert_eq!(header.static_memory_base(), 0x200);
+        assert_eq!(&header.serial(), b"840726");
+        assert_eq!(header.abbreviations(), 0x40);
+        assert_eq!(header.file_length(), 0x200);
+        assert_eq!(header.checksum(), 0xa129);
+        assert_eq!(header.extension(&memory), Some(vec![]));
+    }
+
+    #[test]
+    fn test_header_scales_file_length_by_version() {
+        let mut memory = story(5);
+        memory[0x1a..0x1c].copy_from_slice(&[0x00, 0x80]);
+        assert_eq!(Header::parse(&memory).unwrap().file_length(), 0x200);
+        memory[0] = 8;
+        assert_eq!(Header::parse(&memory).unwrap().file_length(), 0x400);
+        memory[0x1a..0x1c].copy_from_slice(&[0x00, 0x81]);
+        assert_eq!(
+            Header::parse(&memory),
+            Err(HeaderError::Truncated { expected: 0x408, length: 0x400 })
+        );
+    }
+
+    #[test]
+    fn test_header_reads_extension_table() {
+        let mut memory = story(5);
+        memory[0x36..0x38].copy_from_slice(&[0x00, 0x60]);
+        memory[0x60..0x68].copy_from_slice(&[0x00, 0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x70]);
+        let header = Header::parse(&memory).unwrap();
+        assert_eq!(header.extension(&memory), Some(vec![1, 2, 0x70]));
+        assert_eq!(header.extension(&memory[..0x66]), None);
+    }
+
+    #[test]
+    fn test_header_validation() {
+        assert_eq!(Header::parse(&[3; 10]), Err(HeaderError::TooShort { length: 10 }));
+
+        let mut memory = story(9);
+        assert_eq!(Header::parse(&memory), Err(HeaderError::UnsupportedVersion { version: 9 }));
+
+        memory = story(3);
+        memory[0x08..0x0a].copy_from_slice(&[0x10, 0x00]);
+        assert_eq!(
+            Header::parse(&memory),
+            Err(HeaderError::AddressOutOfRange { field: "dictionary", address: 0x1000 })
+        );
+
+        memory = story(3);
+        memory[0x0e..0x10].copy_from_slice(&[0x00, 0x20]);
+        assert_eq!(Header::parse(&memory), Err(HeaderError::StaticMemoryTooLow { address: 0x20 }));
+
+        memory = story(3);
+        memory[0x1a..0x1c].copy_from_slice(&[0x00, 0x1f]);
+        assert_eq!(Header::parse(&memory), Err(HeaderError::FileLengthTooShort { length: 0x3e }));
+        assert_eq!(compute_checksum(&memory), Err(HeaderError::FileLengthTooShort { length: 0x3e }));
+    }
+
+    #[test]
+    fn test_verify_checksum() {
+        let mut memory = story(3);
+        memory[0x40] = 0xff;
+        memory[0x1ff] = 0x02;
+        // beyond the file length, not covered
+        memory[0x200] = 0x07;
+
+        let report = verify(&memory).unwrap();
+        assert_eq!(report.file_length, 0x200);
+        assert_eq!(report.computed, 0x101);
+        assert_eq!(report.expected, 0xa129);
+        assert!(!report.is_valid());
+
+        assert_eq!(write_checksum(&mut memory), Ok(0x101));
+        assert!(verify(&memory).unwrap().is_valid());
+
+        // header fields are not part of the checksum
+        memory[0x10] = 0x01;
+        assert!(verify(&memory).unwrap().is_valid());
+
+        // file length 0 covers the whole file
+        memory[0x1a..0x1c].copy_from_slice(&[0x00, 0x00]);
+        assert_eq!(compute_checksum(&memory), Ok(0x108));
+    }
+
+    #[test]
+    fn test_write_file_length_pads_story_file() {
+        let mut memory = story(5);
+        memory.truncate(0x3fe);
+        memory[0x1a..0x1c].copy_from_slice(&[0x00, 0x00]);
+        assert_eq!(write_file_length(&mut memory), Ok(0x400));
+        assert_eq!(memory.len(), 0x400);
+        assert_eq!(&memory[0x1a..0x1c], &[0x01, 0x00]);
+        assert_eq!(Header::parse(&memory).unwrap().file_length(), 0x400);
+
+        memory[0] = 3;
+        memory.resize(0x1fffe, 0);
+        assert_eq!(write_file_length(&mut memory), Ok(0x1fffe));
+        memory.push(0);
+        assert_eq!(write_file_length(&mut memory), Err(HeaderError::TooLong { length: 0x20000 }));
+    }
+
+}
@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::zscii::ZsciiTable;
+
+/// A key pressed by the player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    /// function keys F1 to F12
+    Function(u8),
+    /// keypad keys 0 to 9
+    Keypad(u8),
+}
+
+impl Key {
+    /// Zscii input code of the key. Chars not defined for
+    /// input (see `ZsciiTable::input_code`) have none.
+    pub fn zscii(&self, zscii: &ZsciiTable) -> Option<u16> {
+        match *self {
+            Key::Char(c) => zscii.input_code(c),
+            Key::Delete => Some(8),
+            Key::Escape => Some(27),
+            Key::Up => Some(129),
+            Key::Down => Some(130),
+            Key::Left => Some(131),
+            Key::Right => Some(132),
+            Key::Function(n @ 1..=12) => Some(132 + n as u16),
+            Key::Keypad(n @ 0..=9) => Some(145 + n as u16),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of waiting for input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input<T> {
+    Ready(T),
+    /// the time given ran out
+    Timeout,
+    /// there is no more input to come
+    Closed,
+}
+
+/// Time source for input timeouts, so that tests can run
+/// timed input without waiting.
+pub trait Clock {
+    /// Time elapsed since some fixed point, in tenths of a
+    /// second.
+    fn tenths(&self) -> u64;
+}
+
+/// The real time, counted from the creation of the clock.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn tenths(&self) -> u64 {
+        (self.start.elapsed().as_millis() / 100) as u64
+    }
+}
+
+/// Input stream 0. `deadline` is a time of `clock` up to
+/// which to wait for input, `None` to wait as long as it
+/// takes.
+pub trait Keyboard {
+    fn read_key(&mut self, clock: &dyn Clock, deadline: Option<u64>) -> Input<Key>;
+
+    /// A line typed by the player, without the newline.
+    fn read_line(&mut self, clock: &dyn Clock, deadline: Option<u64>) -> Input<String>;
+}
+
+/// Input stream 1: a file of commands, one per line, as
+/// recorded by output stream 4. Single keys (for
+/// `read_char`) are taken from the file char by char.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandFile {
+    text: VecDeque<char>,
+}
+
+impl CommandFile {
+    pub fn new(text: &str) -> CommandFile {
+        CommandFile { text: text.chars().collect() }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CommandFile> {
+        Ok(CommandFile::new(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn next_line(&mut self) -> Option<String> {
+        if self.text.is_empty() {
+            return None;
+        }
+        let mut line = String::new();
+        while let Some(c) = self.text.pop_front() {
+            match c {
+                '\n' => break,
+                '\r' => continue,
+                _ => line.push(c),
+            }
+        }
+        Some(line)
+    }
+
+    pub fn next_char(&mut self) -> Option<char> {
+        self.text.pop_front()
+    }
+}
+
+/// Errors from selecting input streams.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputError {
+    /// no input stream with this number
+    InvalidStream { stream: u16 },
+    /// stream 1 selected without a command file
+    NoCommandFile,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::InvalidStream { stream } => {
+                write!(f, "invalid input stream {}", stream)
+            },
+            InputError::NoCommandFile => {
+                write!(f, "no command file for input stream 1")
+            },
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// The input streams: the keyboard (0) and a command file
+/// (1). Once the command file runs out, input comes from the
+/// keyboard again. Input is converted to zscii, dropping
+/// chars that are not defined for input.
+pub struct InputStreams<K: Keyboard, C: Clock> {
+    pub keyboard: K,
+    pub clock: C,
+    pub file: Option<CommandFile>,
+    /// whether stream 1 is selected
+    file_selected: bool,
+}
+
+impl<K: Keyboard, C: Clock> InputStreams<K, C> {
+    pub fn new(keyboard: K, clock: C) -> InputStreams<K, C> {
+        InputStreams { keyboard, clock, file: None, file_selected: false }
+    }
+
+    /// Number of the selected stream. A used up command file
+    /// counts as deselected.
+    pub fn selected(&self) -> u16 {
+        let file_left = self.file.as_ref().is_some_and(|file| !file.is_empty());
+        if self.file_selected && file_left { 1 } else { 0 }
+    }
+
+    pub fn select(&mut self, stream: u16) -> Result<(), InputError> {
+        match stream {
+            0 => self.file_selected = false,
+            1 if self.file.is_none() => return Err(InputError::NoCommandFile),
+            1 => self.file_selected = true,
+            _ => return Err(InputError::InvalidStream { stream }),
+        }
+        Ok(())
+    }
+
+    /// Deadline `timeout` tenths of a second from now (0
+    /// meaning no timeout).
+    fn deadline(&self, timeout: u16) -> Option<u64> {
+        match timeout {
+            0 => None,
+            tenths => Some(self.clock.tenths() + tenths as u64),
+        }
+    }
+
+    /// The command file if selected and not yet used up.
+    fn file(&mut self) -> Option<&mut CommandFile> {
+        if self.file_selected && self.file.as_ref().is_some_and(|file| file.is_empty()) {
+            self.file_selected = false;
+        }
+        match self.file_selected {
+            true => self.file.as_mut(),
+            false => None,
+        }
+    }
+
+    /// Read a line of input as zscii codes, waiting at most
+    /// `timeout` tenths of a second (0 for no limit).
+    pub fn read_line(&mut self, zscii: &ZsciiTable, timeout: u16) -> Input<Vec<u8>> {
+        let line = match self.file().and_then(CommandFile::next_line) {
+            Some(line) => line,
+            None => {
+                let deadline = self.deadline(timeout);
+                match self.keyboard.read_line(&self.clock, deadline) {
+                    Input::Ready(line) => line,
+                    Input::Timeout => return Input::Timeout,
+                    Input::Closed => return Input::Closed,
+                }
+            },
+        };
+        Input::Ready(line.chars()
+            .filter(|&c| c != '\n')
+            .filter_map(|c| zscii.input_code(c))
+            .map(|code| code as u8)
+            .collect())
+    }
+
+    /// Read a single key as zscii code, waiting at most
+    /// `timeout` tenths of a second (0 for no limit). Keys
+    /// without input code are skipped.
+    pub fn read_char(&mut self, zscii: &ZsciiTable, timeout: u16) -> Input<u16> {
+        while let Some(c) = self.file().and_then(CommandFile::next_char) {
+            if let Some(code) = Key::Char(c).zscii(zscii) {
+                return Input::Ready(code);
+            }
+        }
+        let deadline = self.deadline(timeout);
+        loop {
+            match self.keyboard.read_key(&self.clock, deadline) {
+                Input::Ready(key) => if let Some(code) = key.zscii(zscii) {
+                    return Input::Ready(code);
+                },
+                Input::Timeout => return Input::Timeout,
+                Input::Closed => return Input::Closed,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+
+    use super::{Clock, CommandFile, Input, InputError, InputStreams, Key, Keyboard};
+    use crate::zscii::ZsciiTable;
+
+    /// A clock that only moves when told to.
+    #[derive(Default)]
+    pub struct TestClock {
+        pub now: Cell<u64>,
+    }
+
+    impl Clock for TestClock {
+        fn tenths(&self) -> u64 {
+            self.now.get()
+        }
+    }
+
+    /// Keys and lines typed at given times. One that comes
+    /// after the deadline times out and stays queued; the
+    /// clock does not move, tests set it themselves.
+    #[derive(Default)]
+    pub struct TestKeyboard {
+        pub keys: VecDeque<(u64, Key)>,
+        pub lines: VecDeque<(u64, String)>,
+    }
+
+    /// Wait on `clock` for an input typed at `at`.
+    fn wait<T>(clock: &dyn Clock, deadline: Option<u64>, next: Option<(u64, T)>) -> (Input<T>, Option<(u64, T)>) {
+        let now = clock.tenths();
+        match (next, deadline) {
+            (None, _) => (Input::Closed, None),
+            (Some((at, value)), Some(deadline)) if at.max(now) > deadline => {
+                (Input::Timeout, Some((at, value)))
+            },
+            (Some((_, value)), _) => (Input::Ready(value), None),
+        }
+    }
+
+    impl Keyboard for TestKeyboard {
+        fn read_key(&mut self, clock: &dyn Clock, deadline: Option<u64>) -> Input<Key> {
+            let (input, rest) = wait(clock, deadline, self.keys.pop_front());
+            if let Some(key) = rest {
+                self.keys.push_front(key);
+            }
+            input
+        }
+
+        fn read_line(&mut self, clock: &dyn Clock, deadline: Option<u64>) -> Input<String> {
+            let (input, rest) = wait(clock, deadline, self.lines.pop_front());
+            if let Some(line) = rest {
+                self.lines.push_front(line);
+            }
+            input
+        }
+    }
+
+    #[test]
+    fn test_keys_map_to_zscii() {
+        let zscii = ZsciiTable::default();
+        assert_eq!(Key::Char('x').zscii(&zscii), Some(120));
+        assert_eq!(Key::Char('\t').zscii(&zscii), None);
+        assert_eq!(Key::Delete.zscii(&zscii), Some(8));
+        assert_eq!(Key::Up.zscii(&zscii), Some(129));
+        assert_eq!(Key::Function(1).zscii(&zscii), Some(133));
+        assert_eq!(Key::Function(12).zscii(&zscii), Some(144));
+        assert_eq!(Key::Function(13).zscii(&zscii), None);
+        assert_eq!(Key::Keypad(9).zscii(&zscii), Some(154));
+    }
+
+    #[test]
+    fn test_command_file_then_keyboard() {
+        let zscii = ZsciiTable::default();
+        let mut keyboard = TestKeyboard::default();
+        keyboard.lines.push_back((0, "Look\tup".to_string()));
+        let mut input = InputStreams::new(keyboard, TestClock::default());
+
+        assert_eq!(input.select(1), Err(InputError::NoCommandFile));
+        assert_eq!(input.select(2), Err(InputError::InvalidStream { stream: 2 }));
+        input.file = Some(CommandFile::new("north\r\nzü\n"));
+        input.select(1).unwrap();
+
+        assert_eq!(input.read_line(&zscii, 0), Input::Ready(b"north".to_vec()));
+        assert_eq!(input.read_line(&zscii, 0), Input::Ready(vec![b'z', 157]));
+        assert_eq!(input.read_line(&zscii, 0), Input::Ready(b"Lookup".to_vec()));
+        assert_eq!(input.selected(), 0);
+        assert_eq!(input.read_line(&zscii, 0), Input::Closed);
+    }
+
+    #[test]
+    fn test_read_char_times_out() {
+        let zscii = ZsciiTable::default();
+        let mut keyboard = TestKeyboard::default();
+        keyboard.keys.push_back((5, Key::Char('\t')));
+        keyboard.keys.push_back((25, Key::Char('y')));
+        let mut input = InputStreams::new(keyboard, TestClock::default());
+
+        assert_eq!(input.read_char(&zscii, 10), Input::Timeout);
+        input.clock.now.set(10);
+        assert_eq!(input.read_char(&zscii, 10), Input::Timeout);
+        input.clock.now.set(20);
+        assert_eq!(input.read_char(&zscii, 10), Input::Ready(121));
+        assert_eq!(input.read_char(&zscii, 0), Input::Closed);
+    }
+}
@@ -1,4 +1,34 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+// the text codec builds on `core` and `alloc` alone
 pub mod util;
 pub mod zscii;
+pub mod zversion;
+
+#[cfg(feature = "std")]
 pub mod zcode;
-//use zchar::*;
\ No newline at end of file
+#[cfg(feature = "std")]
+pub mod opcodes;
+#[cfg(feature = "std")]
+pub mod header;
+#[cfg(feature = "std")]
+pub mod address;
+#[cfg(feature = "std")]
+pub mod object;
+#[cfg(feature = "std")]
+pub mod dictionary;
+#[cfg(feature = "std")]
+pub mod tokenizer;
+#[cfg(feature = "std")]
+pub mod machine;
+#[cfg(feature = "std")]
+pub mod variable;
+#[cfg(feature = "std")]
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod input;
+//use zchar::*;
@@ -0,0 +1,1303 @@
+use std::fmt;
+
+use crate::address::PackedAddress;
+use crate::dictionary::Dictionary;
+use crate::frame::{Frame, FrameResult, RoutineHeader};
+use crate::header::{compute_checksum, Header, HeaderError};
+use crate::input::{Clock, Input, InputStreams, Keyboard};
+use crate::object::{ObjectError, ObjectTable};
+use crate::opcodes::Mnemonic;
+use crate::stream::{OutputStreams, Screen, StreamError, FLAGS2};
+use crate::tokenizer::tokenise;
+use crate::util::{read_word, write_word, Abbreviations, ZCodec};
+use crate::variable::Variable;
+use crate::zcode::{decode_instruction_with, execute_instruction, ZBranchTarget, ZInstruction, ZOperand};
+use crate::zversion::ZVersion;
+
+/// Errors stopping the machine.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MachineError {
+    /// the story file can not be run
+    Header(HeaderError),
+    /// no valid instruction at `pc`
+    InvalidInstruction { pc: usize },
+    /// the instruction at `pc` is not implemented
+    UnsupportedOpcode { pc: usize, name: &'static str },
+    /// a value was popped off an empty routine stack
+    StackUnderflow { pc: usize },
+    /// the routine at `pc` has no local `variable`
+    InvalidLocal { pc: usize, variable: u8 },
+    DivisionByZero { pc: usize },
+    /// the top level has no routine to return from
+    ReturnFromMain { pc: usize },
+    /// no routine at `address` (too many locals)
+    InvalidRoutine { pc: usize, address: usize },
+    /// `throw` to a frame that is not active
+    InvalidFrame { pc: usize, frame: u16 },
+    /// a read or write outside of memory (writes outside of
+    /// dynamic memory)
+    AddressOutOfRange { pc: usize, address: usize },
+    Object { pc: usize, error: ObjectError },
+    Stream { pc: usize, error: StreamError },
+    /// the timeout routine of an input request at `pc` asked
+    /// for input itself
+    NestedInput { pc: usize },
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MachineError::Header(error) => {
+                write!(f, "{}", error)
+            },
+            MachineError::InvalidInstruction { pc } => {
+                write!(f, "invalid instruction at {:#06x}", pc)
+            },
+            MachineError::UnsupportedOpcode { pc, name } => {
+                write!(f, "unsupported opcode {} at {:#06x}", name, pc)
+            },
+            MachineError::StackUnderflow { pc } => {
+                write!(f, "stack underflow at {:#06x}", pc)
+            },
+            MachineError::InvalidLocal { pc, variable } => {
+                write!(f, "routine has no local {} at {:#06x}", variable, pc)
+            },
+            MachineError::DivisionByZero { pc } => {
+                write!(f, "division by zero at {:#06x}", pc)
+            },
+            MachineError::ReturnFromMain { pc } => {
+                write!(f, "return from main routine at {:#06x}", pc)
+            },
+            MachineError::InvalidRoutine { pc, address } => {
+                write!(f, "no routine at {:#06x} called at {:#06x}", address, pc)
+            },
+            MachineError::InvalidFrame { pc, frame } => {
+                write!(f, "throw to inactive frame {} at {:#06x}", frame, pc)
+            },
+            MachineError::AddressOutOfRange { pc, address } => {
+                write!(f, "access to {:#06x} out of range at {:#06x}", address, pc)
+            },
+            MachineError::Object { pc, error } => {
+                write!(f, "{} at {:#06x}", error, pc)
+            },
+            MachineError::Stream { pc, error } => {
+                write!(f, "{} at {:#06x}", error, pc)
+            },
+            MachineError::NestedInput { pc } => {
+                write!(f, "input requested by the timeout routine of the input at {:#06x}", pc)
+            },
+        }
+    }
+}
+
+impl std::error::Error for MachineError {}
+
+/// What the machine is doing after an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    /// stopped at a `read` (or `read_char`) instruction which
+    /// is left for the caller to complete
+    Input,
+    /// the story executed `quit`
+    Quit,
+    /// a routine called by `Machine::interrupt` returned
+    /// this value
+    Interrupt(u16),
+}
+
+/// A `sread`/`aread` or `read_char` waiting for input. The
+/// program counter already points past the instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InputRequest {
+    /// whether a line is read (or a single key)
+    pub line: bool,
+    /// text and parse buffer of a line read
+    pub text: usize,
+    pub parse: usize,
+    /// tenths of a second after which the packed `routine`
+    /// is called (if both are not 0)
+    pub time: u16,
+    pub routine: u16,
+    pub store: Option<u8>,
+}
+
+/// A running story: its memory, program counter, evaluation
+/// stack and call frames. The first frame stands for the top
+/// level of the story (the main routine in version 6) and
+/// can not be returned from. Screen output goes to `S`,
+/// which by default collects it in a `String`.
+pub struct Machine<S: Screen = String> {
+    pub memory: Vec<u8>,
+    pub header: Header,
+    pub codec: ZCodec,
+    pub abbreviations: Option<Abbreviations>,
+    pub objects: ObjectTable,
+    pub pc: usize,
+    pub stack: Vec<u16>,
+    pub frames: Vec<Frame>,
+    pub streams: OutputStreams<S>,
+    /// story file as loaded, for `restart` and `verify`
+    original: Vec<u8>,
+    random: u64,
+    /// pc of the instruction being executed, for errors
+    current: usize,
+    /// value returned by an interrupt routine, not yet reported
+    interrupted: Option<u16>,
+    /// the read instruction waiting for input
+    pub input: Option<InputRequest>,
+    /// input stream selected by the story
+    pub input_stream: u16,
+}
+
+impl Machine {
+    pub fn new(story: Vec<u8>) -> Result<Machine, MachineError> {
+        Machine::with_screen(story, String::new())
+    }
+}
+
+impl<S: Screen> Machine<S> {
+    pub fn with_screen(story: Vec<u8>, screen: S) -> Result<Machine<S>, MachineError> {
+        let header = Header::parse(&story).map_err(MachineError::Header)?;
+        let codec = ZCodec::from_story(&story).unwrap_or(ZCodec::new(header.version()));
+        let abbreviations = match codec.abbreviation_count() {
+            0 => None,
+            count => Abbreviations::from_table(&story, header.abbreviations() as usize, count),
+        };
+        let mut machine = Machine {
+            memory: story.clone(),
+            streams: OutputStreams::new(screen, header.static_memory_base() as usize),
+            objects: ObjectTable::from_header(&header),
+            header,
+            codec,
+            abbreviations,
+            pc: 0,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            original: story,
+            random: 0x2545f4914f6cdd1d,
+            current: 0,
+            interrupted: None,
+            input: None,
+            input_stream: 0,
+        };
+        machine.reset()?;
+        Ok(machine)
+    }
+
+    /// Start over with the story file as loaded, keeping the
+    /// transcript (bit 0) and fixed pitch (bit 1) settings of
+    /// Flags 2.
+    fn reset(&mut self) -> Result<(), MachineError> {
+        let dynamic = self.header.static_memory_base() as usize;
+        let kept = self.read_word(FLAGS2)? & 0b11;
+        self.memory[..dynamic].copy_from_slice(&self.original[..dynamic]);
+        let flags = self.read_word(FLAGS2)? & !0b11;
+        self.write_word(FLAGS2, flags | kept)?;
+        self.stack.clear();
+        self.frames = vec![Frame::top()];
+        if self.header.version() == ZVersion::V6 {
+            let main = PackedAddress(self.header.initial_pc()).routine(&self.header);
+            self.pc = 0;
+            self.call(main, &[], FrameResult::Discard)?;
+        } else {
+            self.pc = self.header.initial_pc() as usize;
+        }
+        Ok(())
+    }
+
+    pub fn version(&self) -> ZVersion {
+        self.header.version()
+    }
+
+    /// Decode the instruction at the program counter.
+    pub fn fetch(&self) -> Result<ZInstruction, MachineError> {
+        decode_instruction_with(&self.memory, self.pc, self.version(), &self.codec, self.abbreviations.as_ref())
+            .ok_or(MachineError::InvalidInstruction { pc: self.pc })
+    }
+
+    /// Execute the instruction at the program counter, unless
+    /// the machine waits for input.
+    pub fn step(&mut self) -> Result<Status, MachineError> {
+        if self.input.is_some() {
+            return Ok(Status::Input);
+        }
+        let instruction = self.fetch()?;
+        execute_instruction(self, instruction)
+    }
+
+    /// Execute instructions until the story waits for input
+    /// or quits.
+    pub fn run(&mut self) -> Result<Status, MachineError> {
+        loop {
+            match self.step()? {
+                Status::Running => continue,
+                status => return Ok(status),
+            }
+        }
+    }
+
+    fn out_of_range(&self, address: usize) -> MachineError {
+        MachineError::AddressOutOfRange { pc: self.current, address }
+    }
+
+    fn object_error(&self, error: ObjectError) -> MachineError {
+        MachineError::Object { pc: self.current, error }
+    }
+
+    pub fn read_byte(&self, address: usize) -> Result<u8, MachineError> {
+        self.memory.get(address).copied().ok_or(self.out_of_range(address))
+    }
+
+    pub fn read_word(&self, address: usize) -> Result<u16, MachineError> {
+        read_word(&self.memory, address).ok_or(self.out_of_range(address))
+    }
+
+    /// Stories may only write to dynamic memory.
+    fn writable(&self, address: usize, length: usize) -> Result<(), MachineError> {
+        if address + length > self.header.static_memory_base() as usize {
+            return Err(self.out_of_range(address));
+        }
+        Ok(())
+    }
+
+    pub fn write_byte(&mut self, address: usize, value: u8) -> Result<(), MachineError> {
+        self.writable(address, 1)?;
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    pub fn write_word(&mut self, address: usize, value: u16) -> Result<(), MachineError> {
+        self.writable(address, 2)?;
+        write_word(&mut self.memory, address, value).ok_or(self.out_of_range(address))
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("machine without frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("machine without frame")
+    }
+
+    pub fn push(&mut self, value: u16) {
+        self.stack.push(value);
+    }
+
+    /// Pop a value off the current routine's part of the stack.
+    pub fn pop(&mut self) -> Result<u16, MachineError> {
+        if self.stack.len() <= self.frame().stack_base {
+            return Err(MachineError::StackUnderflow { pc: self.current });
+        }
+        Ok(self.stack.pop().unwrap_or(0))
+    }
+
+    fn local(&self, n: u8) -> Result<u16, MachineError> {
+        self.frame().locals.get(n as usize).copied()
+            .ok_or(MachineError::InvalidLocal { pc: self.current, variable: n + 1 })
+    }
+
+    fn set_local(&mut self, n: u8, value: u16) -> Result<(), MachineError> {
+        let pc = self.current;
+        let local = self.frame_mut().locals.get_mut(n as usize)
+            .ok_or(MachineError::InvalidLocal { pc, variable: n + 1 })?;
+        *local = value;
+        Ok(())
+    }
+
+    fn global_address(&self, variable: Variable) -> usize {
+        variable.global_address(self.header.globals()).unwrap_or(0)
+    }
+
+    /// Value of `variable`: 0 pops the stack, 1-15 are the
+    /// current routine's locals and 16-255 the globals.
+    pub fn read_variable(&mut self, variable: u8) -> Result<u16, MachineError> {
+        match Variable::new(variable) {
+            Variable::Stack => self.pop(),
+            Variable::Local(n) => self.local(n),
+            global => self.read_word(self.global_address(global)),
+        }
+    }
+
+    /// Set `variable` (0 pushes onto the stack).
+    pub fn write_variable(&mut self, variable: u8, value: u16) -> Result<(), MachineError> {
+        match Variable::new(variable) {
+            Variable::Stack => {
+                self.push(value);
+                Ok(())
+            },
+            Variable::Local(n) => self.set_local(n, value),
+            global => self.write_word(self.global_address(global), value),
+        }
+    }
+
+    /// Same as `read_variable` except that the stack is only
+    /// peeked at. Instructions taking a variable by number
+    /// (`inc`, `dec`, `inc_chk`, `dec_chk`, `load`, `store`
+    /// and `pull`) access the top of the stack in place.
+    pub fn peek_variable(&self, variable: u8) -> Result<u16, MachineError> {
+        match Variable::new(variable) {
+            Variable::Stack => match self.stack.len() > self.frame().stack_base {
+                true => Ok(self.stack[self.stack.len() - 1]),
+                false => Err(MachineError::StackUnderflow { pc: self.current }),
+            },
+            Variable::Local(n) => self.local(n),
+            global => self.read_word(self.global_address(global)),
+        }
+    }
+
+    /// Same as `write_variable` except that the top of the
+    /// stack is replaced instead of pushing.
+    pub fn replace_variable(&mut self, variable: u8, value: u16) -> Result<(), MachineError> {
+        match Variable::new(variable) {
+            Variable::Stack => {
+                self.peek_variable(0)?;
+                let top = self.stack.len() - 1;
+                self.stack[top] = value;
+                Ok(())
+            },
+            Variable::Local(n) => self.set_local(n, value),
+            global => self.write_word(self.global_address(global), value),
+        }
+    }
+
+    /// Values of the operands of `instruction`, reading
+    /// variable operands in order.
+    fn operand_values(&mut self, instruction: &ZInstruction) -> Result<Vec<u16>, MachineError> {
+        let mut values: Vec<u16> = Vec::new();
+        for operand in instruction.operands().iter().flatten() {
+            let value = match *operand {
+                ZOperand::Variable { value } => self.read_variable(value)?,
+                _ => operand.constant().unwrap_or(0),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    /// Call the routine at byte `address` with `args`, the
+    /// result going to `result`. Calling address 0 just
+    /// returns false.
+    pub fn call(&mut self, address: usize, args: &[u16], result: FrameResult) -> Result<(), MachineError> {
+        if address == 0 {
+            return self.returned(result, 0);
+        }
+        let routine = RoutineHeader::parse(&self.memory, address, self.version())
+            .ok_or(MachineError::InvalidRoutine { pc: self.current, address })?;
+        self.frames.push(Frame::new(&routine, args, self.pc, self.stack.len(), result));
+        self.pc = routine.code;
+        Ok(())
+    }
+
+    /// Call the routine at byte `address` on behalf of the
+    /// interpreter. Running the machine ends with
+    /// `Status::Interrupt` once the routine returns, continuing
+    /// where the machine was interrupted.
+    pub fn interrupt(&mut self, address: usize, args: &[u16]) -> Result<(), MachineError> {
+        self.call(address, args, FrameResult::Interrupt)
+    }
+
+    /// Hand `value` returned by a routine to `result`.
+    fn returned(&mut self, result: FrameResult, value: u16) -> Result<(), MachineError> {
+        match result {
+            FrameResult::Store(variable) => self.write_variable(variable, value),
+            FrameResult::Discard => Ok(()),
+            FrameResult::Interrupt => {
+                self.interrupted = Some(value);
+                Ok(())
+            },
+        }
+    }
+
+    /// Return `value` from the current routine.
+    pub fn ret(&mut self, value: u16) -> Result<(), MachineError> {
+        if self.frames.len() <= 1 {
+            return Err(MachineError::ReturnFromMain { pc: self.current });
+        }
+        let frame = self.frames.pop().expect("machine without frame");
+        self.stack.truncate(frame.stack_base);
+        self.pc = frame.return_pc;
+        self.returned(frame.result, value)
+    }
+
+    /// Identifier of the current frame as returned by `catch`:
+    /// the number of frames below it.
+    pub fn frame_id(&self) -> u16 {
+        (self.frames.len() - 1) as u16
+    }
+
+    /// Return `value` from the frame `frame` (see `frame_id`),
+    /// dropping the frames above it.
+    pub fn throw(&mut self, value: u16, frame: u16) -> Result<(), MachineError> {
+        if frame == 0 || frame > self.frame_id() {
+            return Err(MachineError::InvalidFrame { pc: self.current, frame });
+        }
+        self.frames.truncate(frame as usize + 1);
+        self.ret(value)
+    }
+
+    fn store(&mut self, instruction: &ZInstruction, value: u16) -> Result<(), MachineError> {
+        match instruction.store() {
+            Some(variable) => self.write_variable(variable, value),
+            None => Ok(()),
+        }
+    }
+
+    fn branch(&mut self, instruction: &ZInstruction, condition: bool) -> Result<(), MachineError> {
+        let branch = match instruction.branch() {
+            Some(branch) if branch.on_true == condition => branch,
+            _ => return Ok(()),
+        };
+        match branch.target {
+            ZBranchTarget::ReturnFalse => self.ret(0),
+            ZBranchTarget::ReturnTrue => self.ret(1),
+            ZBranchTarget::Offset(offset) => self.jump(offset as isize),
+        }
+    }
+
+    /// Move the pc by `offset` - 2 bytes as branches and
+    /// `jump` do. A target before the start of memory is
+    /// reported at the pc the jump would have started from.
+    fn jump(&mut self, offset: isize) -> Result<(), MachineError> {
+        self.pc = self.pc.checked_add_signed(offset - 2)
+            .ok_or(self.out_of_range(self.pc))?;
+        Ok(())
+    }
+
+    fn stream_error(&self, error: StreamError) -> MachineError {
+        MachineError::Stream { pc: self.current, error }
+    }
+
+    /// Print `s` to the selected output streams.
+    pub fn print(&mut self, s: &str) -> Result<(), MachineError> {
+        self.streams.print(&mut self.memory, &self.codec.zscii, s).map_err(|e| self.stream_error(e))
+    }
+
+    /// Print the z-string at byte `address`.
+    fn print_at(&mut self, address: usize) -> Result<(), MachineError> {
+        let (s, _) = self.codec.decode_at(&self.memory, address, self.abbreviations.as_ref())
+            .ok_or(self.out_of_range(address))?;
+        self.print(&s)
+    }
+
+    fn print_zscii(&mut self, code: u16) -> Result<(), MachineError> {
+        self.streams.print_zscii(&mut self.memory, &self.codec.zscii, code).map_err(|e| self.stream_error(e))
+    }
+
+    /// Next number of the xorshift generator behind `random`.
+    fn next_random(&mut self) -> u64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        self.random
+    }
+
+    fn random(&mut self, range: i16) -> u16 {
+        match range {
+            1.. => (self.next_random() % range as u64) as u16 + 1,
+            0 => {
+                self.random = 0x2545f4914f6cdd1d ^ self.stack.len() as u64 ^ (self.pc as u64) << 32;
+                0
+            },
+            _ => {
+                self.random = (range as i64).unsigned_abs() | 1 << 63;
+                0
+            },
+        }
+    }
+
+    /// Complete a `sread`/`aread` with `line` (zscii codes) and
+    /// the terminating key `terminator`: the line is stored
+    /// in lower case in the text buffer and, if there is a
+    /// parse buffer, split into words.
+    pub fn finish_line(&mut self, line: &[u8], terminator: u16) -> Result<(), MachineError> {
+        let request = match self.input.take() {
+            Some(request) if request.line => request,
+            request => {
+                self.input = request;
+                return Err(MachineError::InvalidInstruction { pc: self.current });
+            },
+        };
+        let capacity = self.read_byte(request.text)? as usize;
+        let text: Vec<u8> = line.iter().map(u8::to_ascii_lowercase).collect();
+        if self.version() <= ZVersion::V4 {
+            let text = &text[..text.len().min(capacity.saturating_sub(1))];
+            for (n, &c) in text.iter().chain([0u8].iter()).enumerate() {
+                self.write_byte(request.text + 1 + n, c)?;
+            }
+        } else {
+            let text = &text[..text.len().min(capacity)];
+            self.write_byte(request.text + 1, text.len() as u8)?;
+            for (n, &c) in text.iter().enumerate() {
+                self.write_byte(request.text + 2 + n, c)?;
+            }
+        }
+        if request.parse != 0 {
+            let dictionary = Dictionary::from_header(&self.memory, &self.header)
+                .ok_or(self.out_of_range(self.header.dictionary() as usize))?;
+            self.writable(request.parse, 2)?;
+            tokenise(&mut self.memory, request.text, request.parse, &dictionary, &self.codec, false)
+                .ok_or(self.out_of_range(request.parse))?;
+        }
+        if let Some(variable) = request.store {
+            self.write_variable(variable, terminator)?;
+        }
+        Ok(())
+    }
+
+    /// The text in the buffer of the `read` `request` so far.
+    /// Only from version 5 on the buffer tells how much of it
+    /// is in use.
+    fn typed(&self, request: InputRequest) -> Result<Vec<u8>, MachineError> {
+        if self.version() <= ZVersion::V4 {
+            return Ok(Vec::new());
+        }
+        let length = self.read_byte(request.text + 1)? as usize;
+        (0..length).map(|n| self.read_byte(request.text + 2 + n)).collect()
+    }
+
+    /// Complete a `read_char` with the key `code`.
+    pub fn finish_char(&mut self, code: u16) -> Result<(), MachineError> {
+        match self.input.take() {
+            Some(request) if !request.line => match request.store {
+                Some(variable) => self.write_variable(variable, code),
+                None => Ok(()),
+            },
+            request => {
+                self.input = request;
+                Err(MachineError::InvalidInstruction { pc: self.current })
+            },
+        }
+    }
+
+    /// Run the timeout routine of `request`, which tells
+    /// whether to give up waiting for input. Returns the
+    /// status if the routine does not return normally (say,
+    /// the story quits). A routine asking for input itself is
+    /// an error, as the request it interrupts would be lost.
+    /// Aborting `read` keeps the text already in the buffer.
+    fn timed_out(&mut self, request: InputRequest) -> Result<Option<Status>, MachineError> {
+        let pc = self.current;
+        self.input = None;
+        self.interrupt(PackedAddress(request.routine).routine(&self.header), &[])?;
+        let abort = match self.run()? {
+            Status::Interrupt(value) => value != 0,
+            Status::Input => return Err(MachineError::NestedInput { pc }),
+            status => return Ok(Some(status)),
+        };
+        self.input = Some(request);
+        if abort && request.line {
+            let typed = self.typed(request)?;
+            self.finish_line(&typed, 0)?;
+        } else if abort {
+            self.finish_char(0)?;
+        }
+        Ok(None)
+    }
+
+    /// Run the story, reading its input from `input`, until
+    /// it quits or the input is used up (`Status::Input`).
+    /// Lines from the command file are echoed and all lines
+    /// are recorded to output stream 4.
+    pub fn run_with_input<K: Keyboard, C: Clock>(
+        &mut self,
+        input: &mut InputStreams<K, C>
+    ) -> Result<Status, MachineError> {
+        loop {
+            match self.run()? {
+                Status::Input => {},
+                status => return Ok(status),
+            }
+            let request = self.input.expect("input status without request");
+            if input.select(self.input_stream).is_err() {
+                self.input_stream = 0;
+                let _ = input.select(0);
+            }
+            let from_file = input.selected() == 1;
+            let time = if request.routine == 0 { 0 } else { request.time };
+
+            if request.line {
+                let read = input.read_line(&self.codec.zscii, time);
+                self.input_stream = input.selected();
+                match read {
+                    Input::Ready(line) => {
+                        let command: String = line.iter()
+                            .filter_map(|&c| self.codec.zscii.to_char(c as u16))
+                            .collect();
+                        if from_file {
+                            self.print(&command)?;
+                            self.print("\n")?;
+                        }
+                        self.streams.record_command(&command);
+                        self.finish_line(&line, 13)?;
+                    },
+                    Input::Timeout => if let Some(status) = self.timed_out(request)? {
+                        return Ok(status);
+                    },
+                    Input::Closed => return Ok(Status::Input),
+                }
+            } else {
+                let read = input.read_char(&self.codec.zscii, time);
+                self.input_stream = input.selected();
+                match read {
+                    Input::Ready(code) => self.finish_char(code)?,
+                    Input::Timeout => if let Some(status) = self.timed_out(request)? {
+                        return Ok(status);
+                    },
+                    Input::Closed => return Ok(Status::Input),
+                }
+            }
+        }
+    }
+
+    /// Execute `instruction`, which was fetched at the program
+    /// counter. See `zcode::execute_instruction`.
+    pub fn execute(&mut self, instruction: ZInstruction) -> Result<Status, MachineError> {
+        let pc = self.pc;
+        self.current = pc;
+        let info = instruction.info(self.version())
+            .ok_or(MachineError::InvalidInstruction { pc })?;
+        let (mnemonic, name) = (info.mnemonic, info.name);
+        self.pc += instruction.length();
+
+        let operands = self.operand_values(&instruction)?;
+        let a = operands.first().copied().unwrap_or(0);
+        let b = operands.get(1).copied().unwrap_or(0);
+        let i = &instruction;
+        let objects = self.objects;
+
+        match mnemonic {
+            // arithmetic and logic
+            Mnemonic::Add => self.store(i, (a as i16).wrapping_add(b as i16) as u16)?,
+            Mnemonic::Sub => self.store(i, (a as i16).wrapping_sub(b as i16) as u16)?,
+            Mnemonic::Mul => self.store(i, (a as i16).wrapping_mul(b as i16) as u16)?,
+            Mnemonic::Div | Mnemonic::Mod => {
+                if b == 0 {
+                    return Err(MachineError::DivisionByZero { pc });
+                }
+                let value = match mnemonic {
+                    Mnemonic::Div => (a as i16).wrapping_div(b as i16),
+                    _ => (a as i16).wrapping_rem(b as i16),
+                };
+                self.store(i, value as u16)?;
+            },
+            Mnemonic::Or => self.store(i, a | b)?,
+            Mnemonic::And => self.store(i, a & b)?,
+            Mnemonic::Not => self.store(i, !a)?,
+            Mnemonic::LogShift => {
+                let value = match b as i16 {
+                    places @ 0..=15 => a << places,
+                    places @ -15..=-1 => a >> -places,
+                    _ => 0,
+                };
+                self.store(i, value)?;
+            },
+            Mnemonic::ArtShift => {
+                let value = match b as i16 {
+                    places @ 0..=15 => (a as i16) << places,
+                    places @ -15..=-1 => (a as i16) >> -places,
+                    _ => if (a as i16) < 0 { -1 } else { 0 },
+                };
+                self.store(i, value as u16)?;
+            },
+            Mnemonic::Random => {
+                let value = self.random(a as i16);
+                self.store(i, value)?;
+            },
+
+            // branches
+            Mnemonic::Je => self.branch(i, operands.get(1..).unwrap_or(&[]).contains(&a))?,
+            Mnemonic::Jl => self.branch(i, (a as i16) < (b as i16))?,
+            Mnemonic::Jg => self.branch(i, (a as i16) > (b as i16))?,
+            Mnemonic::Jz => self.branch(i, a == 0)?,
+            Mnemonic::Test => self.branch(i, a & b == b)?,
+            Mnemonic::Jump => self.jump((a as i16) as isize)?,
+            Mnemonic::Verify => {
+                let checksum = compute_checksum(&self.original).map_err(MachineError::Header)?;
+                self.branch(i, checksum == self.header.checksum())?;
+            },
+            Mnemonic::Piracy => self.branch(i, true)?,
+
+            // variables and memory
+            Mnemonic::Store => self.replace_variable(a as u8, b)?,
+            Mnemonic::Load => {
+                let value = self.peek_variable(a as u8)?;
+                self.store(i, value)?;
+            },
+            Mnemonic::Inc | Mnemonic::Dec => {
+                let value = self.peek_variable(a as u8)? as i16;
+                let value = if mnemonic == Mnemonic::Inc { value.wrapping_add(1) } else { value.wrapping_sub(1) };
+                self.replace_variable(a as u8, value as u16)?;
+            },
+            Mnemonic::IncChk | Mnemonic::DecChk => {
+                let value = self.peek_variable(a as u8)? as i16;
+                let increment = mnemonic == Mnemonic::IncChk;
+                let value = if increment { value.wrapping_add(1) } else { value.wrapping_sub(1) };
+                self.replace_variable(a as u8, value as u16)?;
+                let condition = if increment { value > b as i16 } else { value < b as i16 };
+                self.branch(i, condition)?;
+            },
+            Mnemonic::Push => self.push(a),
+            Mnemonic::Pull => {
+                let value = self.pop()?;
+                self.replace_variable(a as u8, value)?;
+            },
+            Mnemonic::Pop => {
+                self.pop()?;
+            },
+            Mnemonic::Loadw => {
+                let value = self.read_word(a.wrapping_add(b.wrapping_mul(2)) as usize)?;
+                self.store(i, value)?;
+            },
+            Mnemonic::Loadb => {
+                let value = self.read_byte(a.wrapping_add(b) as usize)?;
+                self.store(i, value as u16)?;
+            },
+            Mnemonic::Storew => {
+                let value = operands.get(2).copied().unwrap_or(0);
+                self.write_word(a.wrapping_add(b.wrapping_mul(2)) as usize, value)?;
+            },
+            Mnemonic::Storeb => {
+                let value = operands.get(2).copied().unwrap_or(0);
+                self.write_byte(a.wrapping_add(b) as usize, value as u8)?;
+            },
+            Mnemonic::CopyTable => {
+                let size = operands.get(2).copied().unwrap_or(0) as i16;
+                let (first, second) = (a as usize, b as usize);
+                let length = size.unsigned_abs() as usize;
+                if second == 0 {
+                    for n in 0..length {
+                        self.write_byte(first + n, 0)?;
+                    }
+                } else if size < 0 || second <= first {
+                    // a negative size copies forwards even if that
+                    // overwrites bytes still to be copied
+                    for n in 0..length {
+                        let byte = self.read_byte(first + n)?;
+                        self.write_byte(second + n, byte)?;
+                    }
+                } else {
+                    for n in (0..length).rev() {
+                        let byte = self.read_byte(first + n)?;
+                        self.write_byte(second + n, byte)?;
+                    }
+                }
+            },
+            Mnemonic::ScanTable => {
+                let length = operands.get(2).copied().unwrap_or(0) as usize;
+                let form = operands.get(3).copied().unwrap_or(0x82);
+                let size = (form & 0x7f) as usize;
+                let mut found = 0;
+                for n in 0..length {
+                    let address = b as usize + n * size;
+                    let value = if form & 0x80 != 0 {
+                        self.read_word(address)?
+                    } else {
+                        self.read_byte(address)? as u16
+                    };
+                    if value == a {
+                        found = address as u16;
+                        break;
+                    }
+                }
+                self.store(i, found)?;
+                self.branch(i, found != 0)?;
+            },
+
+            // routines
+            call if call.is_call() => {
+                let address = PackedAddress(a).routine(&self.header);
+                let result = match instruction.store() {
+                    Some(variable) => FrameResult::Store(variable),
+                    None => FrameResult::Discard,
+                };
+                self.call(address, operands.get(1..).unwrap_or(&[]), result)?;
+            },
+            Mnemonic::Ret => self.ret(a)?,
+            Mnemonic::Rtrue => self.ret(1)?,
+            Mnemonic::Rfalse => self.ret(0)?,
+            Mnemonic::RetPopped => {
+                let value = self.pop()?;
+                self.ret(value)?;
+            },
+            Mnemonic::CheckArgCount => {
+                let supplied = self.frame().has_arg(a);
+                self.branch(i, supplied)?;
+            },
+            Mnemonic::Catch => {
+                let id = self.frame_id();
+                self.store(i, id)?;
+            },
+            Mnemonic::Throw => self.throw(a, b)?,
+
+            // objects
+            Mnemonic::Jin => {
+                let parent = objects.object(&self.memory, a)
+                    .ok_or(self.object_error(ObjectError::InvalidObject { object: a }))?
+                    .parent;
+                self.branch(i, parent == b)?;
+            },
+            Mnemonic::GetParent | Mnemonic::GetSibling | Mnemonic::GetChild => {
+                let object = objects.object(&self.memory, a)
+                    .ok_or(self.object_error(ObjectError::InvalidObject { object: a }))?;
+                let value = match mnemonic {
+                    Mnemonic::GetParent => object.parent,
+                    Mnemonic::GetSibling => object.sibling,
+                    _ => object.child,
+                };
+                self.store(i, value)?;
+                self.branch(i, value != 0)?;
+            },
+            Mnemonic::TestAttr => {
+                let set = objects.test_attr(&self.memory, a, b).map_err(|e| self.object_error(e))?;
+                self.branch(i, set)?;
+            },
+            Mnemonic::SetAttr => {
+                objects.set_attr(&mut self.memory, a, b).map_err(|e| self.object_error(e))?;
+            },
+            Mnemonic::ClearAttr => {
+                objects.clear_attr(&mut self.memory, a, b).map_err(|e| self.object_error(e))?;
+            },
+            Mnemonic::InsertObj => {
+                objects.insert_obj(&mut self.memory, a, b).map_err(|e| self.object_error(e))?;
+            },
+            Mnemonic::RemoveObj => {
+                objects.remove_obj(&mut self.memory, a).map_err(|e| self.object_error(e))?;
+            },
+            Mnemonic::GetProp => {
+                let value = objects.get_prop(&self.memory, a, b).map_err(|e| self.object_error(e))?;
+                self.store(i, value)?;
+            },
+            Mnemonic::GetPropAddr => {
+                let value = objects.get_prop_addr(&self.memory, a, b).map_err(|e| self.object_error(e))?;
+                self.store(i, value as u16)?;
+            },
+            Mnemonic::GetNextProp => {
+                let value = objects.get_next_prop(&self.memory, a, b).map_err(|e| self.object_error(e))?;
+                self.store(i, value)?;
+            },
+            Mnemonic::GetPropLen => {
+                let value = objects.get_prop_len(&self.memory, a as usize).map_err(|e| self.object_error(e))?;
+                self.store(i, value as u16)?;
+            },
+            Mnemonic::PutProp => {
+                let value = operands.get(2).copied().unwrap_or(0);
+                objects.put_prop(&mut self.memory, a, b, value).map_err(|e| self.object_error(e))?;
+            },
+
+            // text
+            Mnemonic::Print => self.print(instruction.text().unwrap_or_default())?,
+            Mnemonic::PrintRet => {
+                self.print(instruction.text().unwrap_or_default())?;
+                self.print("\n")?;
+                self.ret(1)?;
+            },
+            Mnemonic::NewLine => self.print("\n")?,
+            Mnemonic::PrintChar => self.print_zscii(a)?,
+            Mnemonic::PrintUnicode => if let Some(c) = char::from_u32(a as u32) {
+                self.print(c.encode_utf8(&mut [0; 4]))?;
+            },
+            Mnemonic::CheckUnicode => {
+                let known = char::from_u32(a as u32).and_then(|c| self.codec.zscii.to_zscii(c)).is_some();
+                self.store(i, if known { 3 } else { 0 })?;
+            },
+            Mnemonic::PrintNum => self.print(&(a as i16).to_string())?,
+            Mnemonic::PrintAddr => self.print_at(a as usize)?,
+            Mnemonic::PrintPaddr => self.print_at(PackedAddress(a).string(&self.header))?,
+            Mnemonic::PrintObj => {
+                let name = objects.short_name(&self.memory, a, &self.codec, self.abbreviations.as_ref())
+                    .ok_or(self.object_error(ObjectError::InvalidObject { object: a }))?;
+                self.print(&name)?;
+            },
+
+            // input
+            Mnemonic::Sread | Mnemonic::Aread => {
+                self.input = Some(InputRequest {
+                    line: true,
+                    text: a as usize,
+                    parse: b as usize,
+                    time: operands.get(2).copied().unwrap_or(0),
+                    routine: operands.get(3).copied().unwrap_or(0),
+                    store: instruction.store(),
+                });
+                return Ok(Status::Input);
+            },
+            Mnemonic::ReadChar => {
+                self.input = Some(InputRequest {
+                    line: false,
+                    text: 0,
+                    parse: 0,
+                    time: b,
+                    routine: operands.get(2).copied().unwrap_or(0),
+                    store: instruction.store(),
+                });
+                return Ok(Status::Input);
+            },
+            Mnemonic::Tokenise => {
+                let dictionary = match operands.get(2).copied().unwrap_or(0) {
+                    0 => Dictionary::from_header(&self.memory, &self.header),
+                    address => Dictionary::parse(&self.memory, address as usize, self.version()),
+                };
+                let dictionary = dictionary.ok_or(self.out_of_range(self.header.dictionary() as usize))?;
+                let skip_unknown = operands.get(3).copied().unwrap_or(0) != 0;
+                self.writable(b as usize, 2)?;
+                tokenise(&mut self.memory, a as usize, b as usize, &dictionary, &self.codec, skip_unknown)
+                    .ok_or(self.out_of_range(b as usize))?;
+            },
+            Mnemonic::EncodeText => {
+                let length = b as usize;
+                let from = a as usize + operands.get(2).copied().unwrap_or(0) as usize;
+                let text: String = self.memory.get(from..from + length)
+                    .ok_or(self.out_of_range(from))?
+                    .iter()
+                    .filter_map(|&c| self.codec.zscii.to_char(c as u16))
+                    .collect();
+                let encoded = self.codec.encode_dictionary_word(&text);
+                let to = operands.get(3).copied().unwrap_or(0) as usize;
+                for (n, byte) in encoded.into_iter().enumerate() {
+                    self.write_byte(to + n, byte)?;
+                }
+            },
+
+            // the story itself
+            Mnemonic::Quit => {
+                self.pc = pc;
+                return Ok(Status::Quit);
+            },
+            Mnemonic::Restart => self.reset()?,
+            Mnemonic::Save | Mnemonic::Restore => {
+                if self.version() <= ZVersion::V3 {
+                    self.branch(i, false)?;
+                } else {
+                    self.store(i, 0)?;
+                }
+            },
+            // undo is not available: save_undo reports -1,
+            // restore_undo a failure
+            Mnemonic::SaveUndo => self.store(i, 0xffff)?,
+            Mnemonic::RestoreUndo => self.store(i, 0)?,
+            Mnemonic::Nop => {},
+
+            // screen model, sound and streams (not shown yet)
+            Mnemonic::ShowStatus | Mnemonic::SplitWindow | Mnemonic::SetWindow | Mnemonic::EraseWindow
+                | Mnemonic::EraseLine | Mnemonic::SetCursor | Mnemonic::SetTextStyle | Mnemonic::BufferMode
+                | Mnemonic::SetColour | Mnemonic::SetTrueColour | Mnemonic::SoundEffect => {},
+            Mnemonic::InputStream => self.input_stream = a,
+            Mnemonic::OutputStream => {
+                let table = operands.get(1).map(|&table| table as usize);
+                self.streams.select(&mut self.memory, a as i16, table).map_err(|e| self.stream_error(e))?;
+            },
+            Mnemonic::SetFont => self.store(i, 0)?,
+
+            _ => return Err(MachineError::UnsupportedOpcode { pc, name }),
+        }
+        match self.interrupted.take() {
+            Some(value) => Ok(Status::Interrupt(value)),
+            None => Ok(Status::Running),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{Machine, MachineError, Status};
+    use crate::header;
+    use crate::input::tests::{TestClock, TestKeyboard};
+    use crate::input::{CommandFile, InputStreams, Key};
+    use crate::util::string_to_zstring;
+
+    /// The version 3 test story of `header` with `code` at
+    /// the initial pc 0x300 and `routines` at 0x380 and up.
+    pub fn story(code: &[u8], routines: &[u8]) -> Vec<u8> {
+        let mut memory = header::tests::story(3);
+        memory[0x300..0x300 + code.len()].copy_from_slice(code);
+        memory[0x380..0x380 + routines.len()].copy_from_slice(routines);
+        memory
+    }
+
+    #[test]
+    fn test_machine_runs_to_first_prompt() {
+        let mut code = vec![0xb2];
+        code.extend(string_to_zstring("Sum: ".to_string()));
+        code.extend([
+            // call 0x380 (3, 4) -> global 16
+            0xe0, 0x17, 0x01, 0xc0, 0x03, 0x04, 0x10,
+            // print_num g16; new_line
+            0xe6, 0xbf, 0x10, 0xbb,
+            // sread 0x1c0 0x1e0
+            0xe4, 0x0f, 0x01, 0xc0, 0x01, 0xe0,
+        ]);
+        // two locals (initially 0, 10); add l1 l2 -> sp; ret_popped
+        let routine = [0x02, 0x00, 0x00, 0x00, 0x0a, 0x74, 0x01, 0x02, 0x00, 0xb8];
+
+        let mut machine = Machine::new(story(&code, &routine)).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Input));
+        assert_eq!(machine.streams.screen, "Sum: 7\n");
+        assert_eq!(machine.read_word(0x100), Ok(7));
+        assert_eq!(machine.frames.len(), 1);
+        assert!(machine.stack.is_empty());
+    }
+
+    #[test]
+    fn test_machine_loops_and_branches() {
+        let code = [
+            // store g16 3
+            0x0d, 0x10, 0x03,
+            // print_num g16
+            0xe6, 0xbf, 0x10,
+            // dec_chk g16 1 ?~(-6)
+            0x04, 0x10, 0x01, 0x3f, 0xfa,
+            // div 7 0 -> sp
+            0x17, 0x07, 0x00, 0x00,
+        ];
+        let mut machine = Machine::new(story(&code, &[])).unwrap();
+        assert_eq!(machine.run(), Err(MachineError::DivisionByZero { pc: 0x30b }));
+        assert_eq!(machine.streams.screen, "321");
+    }
+
+    #[test]
+    fn test_machine_je_matches_any_operand() {
+        // store g16 7; je <operands> ?+5; print_num 0; quit
+        for (operands, screen) in [
+            // je g16 1 7
+            (&[0x97, 0x10, 0x01, 0x07][..], ""),
+            // je g16 1 2 7
+            (&[0x95, 0x10, 0x01, 0x02, 0x07][..], ""),
+            // je g16 1 2
+            (&[0x97, 0x10, 0x01, 0x02][..], "0"),
+        ] {
+            let mut code = vec![0x0d, 0x10, 0x07, 0xc1];
+            code.extend(operands);
+            code.extend([0xc5, 0xe6, 0x7f, 0x00, 0xba]);
+            let mut machine = Machine::new(story(&code, &[])).unwrap();
+            assert_eq!(machine.run(), Ok(Status::Quit));
+            assert_eq!(machine.streams.screen, screen);
+        }
+    }
+
+    #[test]
+    fn test_machine_copies_overlapping_tables() {
+        for (size, copied) in [([0x00, 0x04], [1, 1, 2, 3, 4]), ([0xff, 0xfc], [1, 1, 1, 1, 1])] {
+            // copy_table 0x180 0x181 size; quit
+            let mut code = vec![0xfd, 0x03, 0x01, 0x80, 0x01, 0x81];
+            code.extend(size);
+            code.push(0xba);
+            let mut memory = story(&code, &[]);
+            memory[0] = 5;
+            memory[0x180..0x184].copy_from_slice(&[1, 2, 3, 4]);
+
+            let mut machine = Machine::new(memory).unwrap();
+            assert_eq!(machine.run(), Ok(Status::Quit));
+            assert_eq!(&machine.memory[0x180..0x185], &copied);
+        }
+    }
+
+    #[test]
+    fn test_machine_restart_keeps_transcript_and_pitch() {
+        let mut machine = Machine::new(story(&[0xba], &[])).unwrap();
+        machine.write_word(0x10, 0x0007).unwrap();
+        machine.write_word(0x100, 5).unwrap();
+        machine.reset().unwrap();
+        assert_eq!(machine.read_word(0x10), Ok(0x0003));
+        assert_eq!(machine.read_word(0x100), Ok(0));
+    }
+
+    #[test]
+    fn test_machine_quits_and_rejects_bad_writes() {
+        // storew 0x300 0 1
+        let mut machine = Machine::new(story(&[0xe1, 0x17, 0x03, 0x00, 0x00, 0x01], &[])).unwrap();
+        assert_eq!(machine.run(), Err(MachineError::AddressOutOfRange { pc: 0x300, address: 0x300 }));
+
+        // push 5; pull g17; quit
+        let mut machine = Machine::new(story(&[0xe8, 0x7f, 0x05, 0xe9, 0x7f, 0x11, 0xba], &[])).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x102), Ok(5));
+    }
+
+    #[test]
+    fn test_machine_accesses_stack_variable_in_place() {
+        let code = [
+            // push 5; push 7; inc sp; dec_chk sp 7 ?rfalse
+            0xe8, 0x7f, 0x05, 0xe8, 0x7f, 0x07, 0x95, 0x00, 0x04, 0x00, 0x07, 0xc0,
+            // load sp -> g16; store sp 9; pull sp
+            0x9e, 0x00, 0x10, 0x0d, 0x00, 0x09, 0xe9, 0x7f, 0x00,
+            // quit
+            0xba,
+        ];
+        let mut machine = Machine::new(story(&code, &[])).unwrap();
+        for _ in 0..4 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.stack, vec![5, 7]);
+        for _ in 0..2 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.read_word(0x100), Ok(7));
+        assert_eq!(machine.stack, vec![5, 9]);
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.stack, vec![9]);
+
+        // inc sp on an empty stack
+        let mut machine = Machine::new(story(&[0x95, 0x00], &[])).unwrap();
+        assert_eq!(machine.step(), Err(MachineError::StackUnderflow { pc: 0x300 }));
+    }
+
+    #[test]
+    fn test_machine_calls_with_seven_arguments() {
+        let code = [
+            // call_vs2 0x380 1 2 3 4 5 6 7 -> g16; quit
+            0xec, 0x15, 0x55, 0x00, 0xe0, 1, 2, 3, 4, 5, 6, 7, 0x10, 0xba,
+        ];
+        let routine = [
+            0x08,
+            // check_arg_count 7 ?~rfalse; check_arg_count 8 ?rfalse
+            0xff, 0x7f, 0x07, 0x40, 0xff, 0x7f, 0x08, 0xc0,
+            // add l1 l7 -> sp; ret_popped
+            0x74, 0x01, 0x07, 0x00, 0xb8,
+        ];
+        let mut memory = story(&code, &routine);
+        memory[0] = 5;
+        let mut machine = Machine::new(memory).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x100), Ok(8));
+    }
+
+    #[test]
+    fn test_machine_call_without_operands_returns_false() {
+        // call -> g16; quit
+        let mut memory = story(&[0xe0, 0xff, 0x10, 0xba], &[]);
+        memory[0x100] = 0xff;
+        let mut machine = Machine::new(memory).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x100), Ok(0));
+    }
+
+    #[test]
+    fn test_machine_jump_before_memory_fails() {
+        // jump #8000
+        let mut machine = Machine::new(story(&[0x8c, 0x80, 0x00], &[])).unwrap();
+        assert_eq!(machine.run(), Err(MachineError::AddressOutOfRange { pc: 0x300, address: 0x303 }));
+    }
+
+    #[test]
+    fn test_machine_catch_and_throw() {
+        // call_vs 0x380 -> g16; quit
+        let code = [0xe0, 0x3f, 0x00, 0xe0, 0x10, 0xba];
+        let mut routines = vec![
+            0x00,
+            // catch -> sp; call_vn 0x390 sp; rtrue
+            0xb9, 0x00, 0xf9, 0x2f, 0x00, 0xe4, 0x00, 0xb0,
+        ];
+        routines.resize(0x10, 0);
+        // one local; push 1; throw 42 l1
+        routines.extend([0x01, 0xe8, 0x7f, 0x01, 0x3c, 42, 0x01]);
+        let mut memory = story(&code, &routines);
+        memory[0] = 5;
+
+        let mut machine = Machine::new(memory).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x100), Ok(42));
+        assert_eq!(machine.frames.len(), 1);
+        assert!(machine.stack.is_empty());
+    }
+
+    #[test]
+    fn test_machine_interrupt_routine() {
+        // one local; ret l1
+        let mut machine = Machine::new(story(&[0xba], &[0x01, 0x00, 0x00, 0xab, 0x01])).unwrap();
+        machine.interrupt(0x380, &[5]).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Interrupt(5)));
+        assert_eq!(machine.pc, 0x300);
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(machine.throw(0, 3), Err(MachineError::InvalidFrame { pc: 0x300, frame: 3 }));
+    }
+
+    #[test]
+    fn test_machine_redirects_output_to_table() {
+        // output_stream 3 0x180; print "hi"; output_stream -3; print "!"; quit
+        let mut code = vec![0xf3, 0x4f, 0x03, 0x01, 0x80, 0xb2];
+        code.extend(string_to_zstring("hi".to_string()));
+        code.extend([0xf3, 0x3f, 0xff, 0xfd, 0xb2]);
+        code.extend(string_to_zstring("!".to_string()));
+        code.push(0xba);
+
+        let mut machine = Machine::new(story(&code, &[])).unwrap();
+        assert_eq!(machine.run(), Ok(Status::Quit));
+        assert_eq!(&machine.memory[0x180..0x184], &[0, 2, b'h', b'i']);
+        assert_eq!(machine.streams.screen, "!");
+    }
+
+    #[test]
+    fn test_machine_reads_commands_from_file() {
+        let code = [
+            // input_stream 1; sread 0x1c0 0x1e0
+            0xf4, 0x7f, 0x01, 0xe4, 0x0f, 0x01, 0xc0, 0x01, 0xe0,
+            // loadb 0x1e0 1 -> sp; print_num sp; sread 0x1c0 0
+            0xd0, 0x1f, 0x01, 0xe0, 0x01, 0x00, 0xe6, 0xbf, 0x00, 0xe4, 0x1f, 0x01, 0xc0, 0x00,
+        ];
+        let mut memory = story(&code, &[]);
+        memory[0x1c0] = 20;
+        memory[0x1e0] = 4;
+        let mut machine = Machine::new(memory).unwrap();
+        let mut input = InputStreams::new(TestKeyboard::default(), TestClock::default());
+        input.file = Some(CommandFile::new("Open Mailbox\n"));
+
+        assert_eq!(machine.run_with_input(&mut input), Ok(Status::Input));
+        assert_eq!(machine.streams.screen, "Open Mailbox\n2");
+        assert_eq!(&machine.memory[0x1c1..0x1ce], b"open mailbox\0");
+        assert_eq!(machine.input_stream, 0);
+        assert!(machine.input.is_some());
+    }
+
+    #[test]
+    fn test_machine_read_char_timeout_routine() {
+        // read_char 1 10 0x380 -> g16; quit
+        let code = [0xf6, 0x53, 0x01, 0x0a, 0x00, 0xe0, 0x10, 0xba];
+        // rtrue
+        let mut memory = story(&code, &[0x00, 0xb0]);
+        memory[0] = 5;
+        memory[0x100] = 0xff;
+        let mut machine = Machine::new(memory).unwrap();
+        let mut keyboard = TestKeyboard::default();
+        keyboard.keys.push_back((50, Key::Char('x')));
+        let mut input = InputStreams::new(keyboard, TestClock::default());
+
+        assert_eq!(machine.run_with_input(&mut input), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x100), Ok(0));
+        assert_eq!(input.keyboard.keys.len(), 1);
+    }
+
+    #[test]
+    fn test_machine_aborted_read_keeps_typed_text() {
+        // aread 0x1c0 0 10 0x380 -> g16; quit
+        let code = [0xe4, 0x14, 0x01, 0xc0, 0x00, 0x0a, 0x00, 0xe0, 0x10, 0xba];
+        // rtrue
+        let mut memory = story(&code, &[0x00, 0xb0]);
+        memory[0] = 5;
+        memory[0x100] = 0xff;
+        memory[0x1c0..0x1c4].copy_from_slice(&[20, 2, b'a', b'b']);
+        let mut machine = Machine::new(memory).unwrap();
+        let mut keyboard = TestKeyboard::default();
+        keyboard.lines.push_back((50, "x".to_string()));
+        let mut input = InputStreams::new(keyboard, TestClock::default());
+
+        assert_eq!(machine.run_with_input(&mut input), Ok(Status::Quit));
+        assert_eq!(machine.read_word(0x100), Ok(0));
+        assert_eq!(&machine.memory[0x1c0..0x1c4], &[20, 2, b'a', b'b']);
+    }
+
+    #[test]
+    fn test_machine_timeout_routine_must_not_read() {
+        // read_char 1 10 0x380 -> g16; quit
+        let code = [0xf6, 0x53, 0x01, 0x0a, 0x00, 0xe0, 0x10, 0xba];
+        // read_char 1 -> sp; rtrue
+        let mut memory = story(&code, &[0x00, 0xf6, 0x7f, 0x01, 0x00, 0xb0]);
+        memory[0] = 5;
+        let mut machine = Machine::new(memory).unwrap();
+        let mut keyboard = TestKeyboard::default();
+        keyboard.keys.push_back((50, Key::Char('x')));
+        let mut input = InputStreams::new(keyboard, TestClock::default());
+
+        assert_eq!(machine.run_with_input(&mut input), Err(MachineError::NestedInput { pc: 0x300 }));
+    }
+}
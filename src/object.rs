@@ -0,0 +1,641 @@
+use std::fmt;
+
+use crate::header::Header;
+use crate::util::{read_word, write_word, Abbreviations, ZCodec};
+use crate::zversion::ZVersion;
+
+/// Location and layout of a story's object table. The table
+/// starts with the property defaults (31 words in versions
+/// 1-3, 63 words since version 4) followed by the object
+/// entries. Objects are numbered from 1, object 0 means
+/// "nothing". The table does not own the memory it refers to,
+/// so the same value serves for reading and (see below)
+/// modifying the object tree.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObjectTable {
+    version: ZVersion,
+    address: usize,
+}
+
+/// Errors from object tree operations.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectError {
+    /// object 0 or a number beyond what the layout can address
+    InvalidObject { object: u16 },
+    /// attribute number beyond 31 (since version 4: 47)
+    InvalidAttribute { attribute: u16 },
+    /// property number 0 or beyond 31 (since version 4: 63)
+    InvalidProperty { property: u16 },
+    /// the object does not provide the property
+    MissingProperty { object: u16, property: u16 },
+    /// the property is neither 1 nor 2 bytes long, so it can
+    /// not be read or written as a value
+    PropertyLength { object: u16, property: u16, length: usize },
+    /// the move would make an object its own ancestor
+    TreeLoop { object: u16, destination: u16 },
+    /// the parent or sibling links around `object` run in a
+    /// circle
+    CorruptTree { object: u16 },
+    /// the table refers to memory beyond the story
+    AddressOutOfRange { address: usize },
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectError::InvalidObject { object } => {
+                write!(f, "invalid object {}", object)
+            },
+            ObjectError::InvalidAttribute { attribute } => {
+                write!(f, "invalid attribute {}", attribute)
+            },
+            ObjectError::InvalidProperty { property } => {
+                write!(f, "invalid property {}", property)
+            },
+            ObjectError::MissingProperty { object, property } => {
+                write!(f, "object {} has no property {}", object, property)
+            },
+            ObjectError::PropertyLength { object, property, length } => {
+                write!(f, "property {} of object {} has {} bytes, expected 1 or 2", property, object, length)
+            },
+            ObjectError::TreeLoop { object, destination } => {
+                write!(f, "can not move object {} into its descendant {}", object, destination)
+            },
+            ObjectError::CorruptTree { object } => {
+                write!(f, "object tree around object {} runs in a circle", object)
+            },
+            ObjectError::AddressOutOfRange { address } => {
+                write!(f, "object table address {:#06x} lies outside the story file", address)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+/// An object entry: its attribute flags, its relatives in
+/// the object tree and the address of its property table.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZObject {
+    pub number: u16,
+    /// attribute flags, attribute 0 is the top bit of the
+    /// first byte (versions 1-3 only use the first 4 bytes)
+    pub attributes: [u8; 6],
+    pub parent: u16,
+    pub sibling: u16,
+    pub child: u16,
+    pub properties: usize,
+}
+
+impl ZObject {
+    pub fn has_attribute(&self, attribute: u8) -> bool {
+        match self.attributes.get(attribute as usize / 8) {
+            Some(b) => b & (0x80 >> (attribute % 8)) != 0,
+            None => false,
+        }
+    }
+}
+
+/// A property of an object: its number and where its data
+/// (of `length` bytes) is located.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZProperty {
+    pub number: u8,
+    pub address: usize,
+    pub length: usize,
+}
+
+impl ZProperty {
+    pub fn data<'a>(&self, memory: &'a [u8]) -> Option<&'a [u8]> {
+        memory.get(self.address..self.address + self.length)
+    }
+}
+
+/// Number and data length of the property whose size byte(s)
+/// start at `address`, and the number of size bytes. `None`
+/// at the end of the property list (a size byte of 0).
+fn property_header(memory: &[u8], version: ZVersion, address: usize) -> Option<(u8, usize, usize)> {
+    let b = *memory.get(address)?;
+    if b == 0 {
+        return None;
+    }
+    if version <= ZVersion::V3 {
+        return Some((b & 0x1f, (b >> 5) as usize + 1, 1));
+    }
+    if b & 0x80 != 0 {
+        let length = match *memory.get(address + 1)? & 0x3f {
+            0 => 64,
+            n => n as usize,
+        };
+        return Some((b & 0x3f, length, 2));
+    }
+    let length = if b & 0x40 != 0 { 2 } else { 1 };
+    Some((b & 0x3f, length, 1))
+}
+
+/// Data length of the property whose data starts at
+/// `address`, as `get_prop_len` computes it from the size
+/// byte(s) preceding the data. Address 0 yields 0.
+pub fn property_length(memory: &[u8], version: ZVersion, address: usize) -> Option<usize> {
+    if address == 0 {
+        return Some(0);
+    }
+    let b = *memory.get(address - 1)?;
+    if version <= ZVersion::V3 {
+        return Some((b >> 5) as usize + 1);
+    }
+    if b & 0x80 != 0 {
+        return match b & 0x3f {
+            0 => Some(64),
+            n => Some(n as usize),
+        };
+    }
+    Some(if b & 0x40 != 0 { 2 } else { 1 })
+}
+
+/// Iterator over the property list of an object, in the
+/// order stored (which is descending by property number).
+#[derive(Clone, Debug)]
+pub struct Properties<'a> {
+    memory: &'a [u8],
+    version: ZVersion,
+    address: usize,
+}
+
+impl Iterator for Properties<'_> {
+    type Item = ZProperty;
+
+    fn next(&mut self) -> Option<ZProperty> {
+        let (number, length, size) = property_header(self.memory, self.version, self.address)?;
+        let property = ZProperty { number, address: self.address + size, length };
+        self.address = property.address + length;
+        Some(property)
+    }
+}
+
+impl ObjectTable {
+    pub fn new(version: ZVersion, address: usize) -> ObjectTable {
+        ObjectTable { version, address }
+    }
+
+    pub fn from_header(header: &Header) -> ObjectTable {
+        ObjectTable::new(header.version(), header.object_table() as usize)
+    }
+
+    pub fn version(&self) -> ZVersion {
+        self.version
+    }
+
+    /// Number of property defaults preceding the objects.
+    fn defaults_count(&self) -> usize {
+        if self.version <= ZVersion::V3 { 31 } else { 63 }
+    }
+
+    /// Size of an object entry in bytes.
+    fn entry_size(&self) -> usize {
+        if self.version <= ZVersion::V3 { 9 } else { 14 }
+    }
+
+    /// Highest object number the layout can address.
+    pub fn max_objects(&self) -> u16 {
+        if self.version <= ZVersion::V3 { 255 } else { 65535 }
+    }
+
+    /// Default value of `property` (1-31, since version 4 1-63)
+    /// used for objects that do not provide the property.
+    pub fn property_default(&self, memory: &[u8], property: u8) -> Option<u16> {
+        if property == 0 || property as usize > self.defaults_count() {
+            return None;
+        }
+        read_word(memory, self.address + 2 * (property as usize - 1))
+    }
+
+    /// Byte address of the entry of `object`.
+    pub fn entry_address(&self, object: u16) -> Option<usize> {
+        if object == 0 || object > self.max_objects() {
+            return None;
+        }
+        Some(self.address + 2 * self.defaults_count() + self.entry_size() * (object as usize - 1))
+    }
+
+    /// Number of objects in the table. The table does not
+    /// store it, so by convention the objects are assumed to
+    /// end where the first property table begins.
+    pub fn count(&self, memory: &[u8]) -> u16 {
+        let first = match self.entry_address(1) {
+            Some(address) => address,
+            None => return 0,
+        };
+        let end = match self.object(memory, 1) {
+            Some(o) if o.properties > first => o.properties,
+            _ => return 0,
+        };
+        ((end - first) / self.entry_size()).min(self.max_objects() as usize) as u16
+    }
+
+    pub fn object(&self, memory: &[u8], object: u16) -> Option<ZObject> {
+        let address = self.entry_address(object)?;
+        let entry = memory.get(address..address + self.entry_size())?;
+        let mut attributes = [0u8; 6];
+        if self.version <= ZVersion::V3 {
+            attributes[..4].copy_from_slice(&entry[..4]);
+            Some(ZObject {
+                number: object,
+                attributes,
+                parent: entry[4] as u16,
+                sibling: entry[5] as u16,
+                child: entry[6] as u16,
+                properties: read_word(entry, 7)? as usize,
+            })
+        } else {
+            attributes.copy_from_slice(&entry[..6]);
+            Some(ZObject {
+                number: object,
+                attributes,
+                parent: read_word(entry, 6)?,
+                sibling: read_word(entry, 8)?,
+                child: read_word(entry, 10)?,
+                properties: read_word(entry, 12)? as usize,
+            })
+        }
+    }
+
+    /// Short name of `object`, stored as z-string at the start
+    /// of its property table (after a byte giving its length
+    /// in words).
+    pub fn short_name(
+        &self,
+        memory: &[u8],
+        object: u16,
+        codec: &ZCodec,
+        abbreviations: Option<&Abbreviations>
+    ) -> Option<String> {
+        let address = self.object(memory, object)?.properties;
+        if *memory.get(address)? == 0 {
+            return Some(String::new());
+        }
+        let (name, _) = codec.decode_at(memory, address + 1, abbreviations)?;
+        Some(name)
+    }
+
+    /// Properties of `object`, following its short name.
+    pub fn properties<'a>(&self, memory: &'a [u8], object: u16) -> Option<Properties<'a>> {
+        let address = self.object(memory, object)?.properties;
+        let name_length = *memory.get(address)? as usize;
+        Some(Properties { memory, version: self.version, address: address + 1 + 2 * name_length })
+    }
+
+    /// Children of `object` in tree order (following the
+    /// sibling chain of its first child).
+    pub fn children(&self, memory: &[u8], object: u16) -> Option<Vec<u16>> {
+        let mut children: Vec<u16> = Vec::new();
+        let mut child = self.object(memory, object)?.child;
+        while child != 0 {
+            // guard against cyclic sibling chains
+            if children.contains(&child) {
+                return None;
+            }
+            children.push(child);
+            child = self.object(memory, child)?.sibling;
+        }
+        Some(children)
+    }
+}
+
+/// The object operations of the instruction set, working on
+/// a story memory image. Property values and tree links are
+/// read and written in place.
+impl ObjectTable {
+    fn entry(&self, memory: &[u8], object: u16) -> Result<ZObject, ObjectError> {
+        let address = self.entry_address(object).ok_or(ObjectError::InvalidObject { object })?;
+        self.object(memory, object).ok_or(ObjectError::AddressOutOfRange { address })
+    }
+
+    /// Byte address and bit mask of `attribute` of `object`.
+    fn attribute_bit(&self, memory: &[u8], object: u16, attribute: u16) -> Result<(usize, u8), ObjectError> {
+        let count = if self.version <= ZVersion::V3 { 32 } else { 48 };
+        if attribute >= count {
+            return Err(ObjectError::InvalidAttribute { attribute });
+        }
+        self.entry(memory, object)?;
+        let address = self.entry_address(object).ok_or(ObjectError::InvalidObject { object })?;
+        Ok((address + attribute as usize / 8, 0x80 >> (attribute % 8)))
+    }
+
+    pub fn test_attr(&self, memory: &[u8], object: u16, attribute: u16) -> Result<bool, ObjectError> {
+        let (address, mask) = self.attribute_bit(memory, object, attribute)?;
+        Ok(memory[address] & mask != 0)
+    }
+
+    pub fn set_attr(&self, memory: &mut [u8], object: u16, attribute: u16) -> Result<(), ObjectError> {
+        let (address, mask) = self.attribute_bit(memory, object, attribute)?;
+        memory[address] |= mask;
+        Ok(())
+    }
+
+    pub fn clear_attr(&self, memory: &mut [u8], object: u16, attribute: u16) -> Result<(), ObjectError> {
+        let (address, mask) = self.attribute_bit(memory, object, attribute)?;
+        memory[address] &= !mask;
+        Ok(())
+    }
+
+    /// The property `property` of `object`, if it has one.
+    pub fn find_property(&self, memory: &[u8], object: u16, property: u16) -> Result<Option<ZProperty>, ObjectError> {
+        if property == 0 || property as usize > self.defaults_count() {
+            return Err(ObjectError::InvalidProperty { property });
+        }
+        let o = self.entry(memory, object)?;
+        let mut properties = self.properties(memory, object)
+            .ok_or(ObjectError::AddressOutOfRange { address: o.properties })?;
+        Ok(properties.find(|p| p.number as u16 == property))
+    }
+
+    /// Value of `property` of `object`, or the property
+    /// default if the object does not provide it.
+    pub fn get_prop(&self, memory: &[u8], object: u16, property: u16) -> Result<u16, ObjectError> {
+        match self.find_property(memory, object, property)? {
+            None => Ok(self.property_default(memory, property as u8).unwrap_or(0)),
+            Some(p) => match p.data(memory) {
+                Some([b]) => Ok(*b as u16),
+                Some([h, l]) => Ok(u16::from_be_bytes([*h, *l])),
+                Some(_) => Err(ObjectError::PropertyLength { object, property, length: p.length }),
+                None => Err(ObjectError::AddressOutOfRange { address: p.address }),
+            },
+        }
+    }
+
+    /// Set `property` of `object`, which it has to provide. A
+    /// 1 byte property receives the low byte of `value`.
+    pub fn put_prop(&self, memory: &mut [u8], object: u16, property: u16, value: u16) -> Result<(), ObjectError> {
+        let p = self.find_property(memory, object, property)?
+            .ok_or(ObjectError::MissingProperty { object, property })?;
+        let stored = match p.length {
+            1 => memory.get_mut(p.address).map(|b| *b = value as u8),
+            2 => write_word(memory, p.address, value),
+            length => return Err(ObjectError::PropertyLength { object, property, length }),
+        };
+        stored.ok_or(ObjectError::AddressOutOfRange { address: p.address })
+    }
+
+    /// Data address of `property` of `object`, 0 if absent.
+    pub fn get_prop_addr(&self, memory: &[u8], object: u16, property: u16) -> Result<usize, ObjectError> {
+        Ok(self.find_property(memory, object, property)?.map_or(0, |p| p.address))
+    }
+
+    /// Length of the property data at `address` (as returned
+    /// by `get_prop_addr`).
+    pub fn get_prop_len(&self, memory: &[u8], address: usize) -> Result<usize, ObjectError> {
+        property_length(memory, self.version, address).ok_or(ObjectError::AddressOutOfRange { address })
+    }
+
+    /// Number of the property following `property` in the
+    /// property list of `object` (the first one for 0), or 0
+    /// after the last one.
+    pub fn get_next_prop(&self, memory: &[u8], object: u16, property: u16) -> Result<u16, ObjectError> {
+        let o = self.entry(memory, object)?;
+        let mut properties = self.properties(memory, object)
+            .ok_or(ObjectError::AddressOutOfRange { address: o.properties })?;
+        if property != 0 && self.find_property(memory, object, property)?.is_none() {
+            return Err(ObjectError::MissingProperty { object, property });
+        }
+        if property != 0 {
+            properties.find(|p| p.number as u16 == property);
+        }
+        Ok(properties.next().map_or(0, |p| p.number as u16))
+    }
+
+    /// Rewrite the parent (0), sibling (1) or child (2) link
+    /// of `object`.
+    fn set_link(&self, memory: &mut [u8], object: u16, link: usize, value: u16) -> Result<(), ObjectError> {
+        let address = self.entry_address(object).ok_or(ObjectError::InvalidObject { object })?;
+        let stored = if self.version <= ZVersion::V3 {
+            memory.get_mut(address + 4 + link).map(|b| *b = value as u8)
+        } else {
+            write_word(memory, address + 6 + 2 * link, value)
+        };
+        stored.ok_or(ObjectError::AddressOutOfRange { address })
+    }
+
+    /// Detach `object` from its parent, keeping its children.
+    pub fn remove_obj(&self, memory: &mut [u8], object: u16) -> Result<(), ObjectError> {
+        let o = self.entry(memory, object)?;
+        if o.parent == 0 {
+            return Ok(());
+        }
+        let parent = self.entry(memory, o.parent)?;
+        if parent.child == object {
+            self.set_link(memory, o.parent, 2, o.sibling)?;
+        } else {
+            // a chain longer than the number of objects runs
+            // in a circle
+            let mut previous = parent.child;
+            let mut steps = 0;
+            while previous != 0 {
+                if steps == self.max_objects() {
+                    return Err(ObjectError::CorruptTree { object });
+                }
+                let p = self.entry(memory, previous)?;
+                if p.sibling == object {
+                    self.set_link(memory, previous, 1, o.sibling)?;
+                    break;
+                }
+                previous = p.sibling;
+                steps += 1;
+            }
+        }
+        self.set_link(memory, object, 0, 0)?;
+        self.set_link(memory, object, 1, 0)
+    }
+
+    /// Make `object` the first child of `destination`.
+    pub fn insert_obj(&self, memory: &mut [u8], object: u16, destination: u16) -> Result<(), ObjectError> {
+        self.entry(memory, object)?;
+        let mut ancestor = destination;
+        let mut steps = 0;
+        while ancestor != 0 {
+            if ancestor == object {
+                return Err(ObjectError::TreeLoop { object, destination });
+            }
+            if steps == self.max_objects() {
+                return Err(ObjectError::CorruptTree { object: destination });
+            }
+            ancestor = self.entry(memory, ancestor)?.parent;
+            steps += 1;
+        }
+        self.remove_obj(memory, object)?;
+        let first = self.entry(memory, destination)?.child;
+        self.set_link(memory, object, 1, first)?;
+        self.set_link(memory, object, 0, destination)?;
+        self.set_link(memory, destination, 2, object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{property_length, ObjectError, ObjectTable, ZProperty};
+    use crate::util::{string_to_zstring, ZCodec};
+    use crate::zversion::ZVersion;
+
+    /// Version 3 object table at 0x40 with three objects:
+    /// 1 "room" containing 2 "lamp" and 3 "box".
+    fn story_v3() -> Vec<u8> {
+        let mut memory = vec![0u8; 0x100];
+        let table = ObjectTable::new(ZVersion::V3, 0x40);
+        memory[0x40..0x42].copy_from_slice(&[0x00, 0x07]);
+
+        let entries: [(u8, u8, u8, u8, u16); 3] = [
+            (0x00, 0, 0, 2, 0x99),
+            (0x80, 1, 3, 0, 0xb0),
+            (0x01, 1, 0, 0, 0xc0),
+        ];
+        for (n, (attribute, parent, sibling, child, props)) in entries.iter().enumerate() {
+            let address = table.entry_address(n as u16 + 1).unwrap();
+            memory[address] = *attribute;
+            memory[address + 4] = *parent;
+            memory[address + 5] = *sibling;
+            memory[address + 6] = *child;
+            memory[address + 7..address + 9].copy_from_slice(&props.to_be_bytes());
+        }
+        for (address, name) in [(0x99, "room"), (0xb0, "lamp"), (0xc0, "box")] {
+            let z = string_to_zstring(name.to_string());
+            memory[address] = (z.len() / 2) as u8;
+            memory[address + 1..address + 1 + z.len()].copy_from_slice(&z);
+        }
+        // lamp: property 5 (2 bytes), property 2 (1 byte)
+        memory[0xb5..0xbb].copy_from_slice(&[0x25, 0x12, 0x34, 0x02, 0x56, 0x00]);
+        memory
+    }
+
+    #[test]
+    fn test_object_table_reads_v3_objects() {
+        let memory = story_v3();
+        let table = ObjectTable::new(ZVersion::V3, 0x40);
+        let codec = ZCodec::new(ZVersion::V3);
+
+        assert_eq!(table.count(&memory), 3);
+        assert_eq!(table.property_default(&memory, 1), Some(7));
+        assert_eq!(table.property_default(&memory, 32), None);
+
+        let lamp = table.object(&memory, 2).unwrap();
+        assert_eq!((lamp.parent, lamp.sibling, lamp.child), (1, 3, 0));
+        assert!(lamp.has_attribute(0));
+        assert!(!lamp.has_attribute(1));
+        assert!(table.object(&memory, 3).unwrap().has_attribute(7));
+        assert_eq!(table.short_name(&memory, 2, &codec, None), Some("lamp".to_string()));
+        assert_eq!(table.children(&memory, 1), Some(vec![2, 3]));
+        assert_eq!(table.object(&memory, 0), None);
+
+        let properties: Vec<ZProperty> = table.properties(&memory, 2).unwrap().collect();
+        assert_eq!(properties, vec![
+            ZProperty { number: 5, address: 0xb6, length: 2 },
+            ZProperty { number: 2, address: 0xb9, length: 1 },
+        ]);
+        assert_eq!(properties[0].data(&memory), Some(&[0x12, 0x34][..]));
+        assert_eq!(property_length(&memory, ZVersion::V3, 0xb6), Some(2));
+        assert_eq!(table.properties(&memory, 3).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_object_table_reads_v4_objects() {
+        let mut memory = vec![0u8; 0x200];
+        let table = ObjectTable::new(ZVersion::V4, 0x40);
+        let address = table.entry_address(1).unwrap();
+        assert_eq!(address, 0x40 + 126);
+
+        memory[address + 5] = 0x01;
+        memory[address + 6..address + 14]
+            .copy_from_slice(&[0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x01, 0x80]);
+        // no short name, property 40 (2 bytes), property 3
+        // (long form, 10 bytes), property 1 (long form, 64 bytes)
+        memory[0x180..0x186].copy_from_slice(&[0x00, 0x68, 0xaa, 0xbb, 0x83, 0x8a]);
+        memory[0x190..0x192].copy_from_slice(&[0x81, 0x80]);
+
+        let object = table.object(&memory, 1).unwrap();
+        assert_eq!((object.parent, object.sibling, object.child), (0x100, 0x200, 0x300));
+        assert!(object.has_attribute(47));
+        assert_eq!(table.short_name(&memory, 1, &ZCodec::new(ZVersion::V4), None), Some(String::new()));
+
+        let properties: Vec<ZProperty> = table.properties(&memory, 1).unwrap().collect();
+        assert_eq!(properties, vec![
+            ZProperty { number: 40, address: 0x182, length: 2 },
+            ZProperty { number: 3, address: 0x186, length: 10 },
+            ZProperty { number: 1, address: 0x192, length: 64 },
+        ]);
+        assert_eq!(property_length(&memory, ZVersion::V4, 0x186), Some(10));
+        assert_eq!(property_length(&memory, ZVersion::V4, 0x192), Some(64));
+        assert_eq!(property_length(&memory, ZVersion::V4, 0), Some(0));
+    }
+
+    #[test]
+    fn test_object_attributes_and_properties() {
+        let mut memory = story_v3();
+        let table = ObjectTable::new(ZVersion::V3, 0x40);
+
+        assert_eq!(table.test_attr(&memory, 2, 0), Ok(true));
+        table.set_attr(&mut memory, 2, 31).unwrap();
+        table.clear_attr(&mut memory, 2, 0).unwrap();
+        assert_eq!(table.test_attr(&memory, 2, 31), Ok(true));
+        assert_eq!(table.test_attr(&memory, 2, 0), Ok(false));
+        assert_eq!(table.set_attr(&mut memory, 2, 32), Err(ObjectError::InvalidAttribute { attribute: 32 }));
+        assert_eq!(table.test_attr(&memory, 0, 1), Err(ObjectError::InvalidObject { object: 0 }));
+
+        assert_eq!(table.get_prop(&memory, 2, 5), Ok(0x1234));
+        assert_eq!(table.get_prop(&memory, 2, 2), Ok(0x56));
+        assert_eq!(table.get_prop(&memory, 2, 1), Ok(7));
+        table.put_prop(&mut memory, 2, 2, 0xabcd).unwrap();
+        assert_eq!(table.get_prop(&memory, 2, 2), Ok(0xcd));
+        assert_eq!(table.put_prop(&mut memory, 2, 3, 1), Err(ObjectError::MissingProperty { object: 2, property: 3 }));
+        assert_eq!(table.get_prop(&memory, 2, 0), Err(ObjectError::InvalidProperty { property: 0 }));
+
+        assert_eq!(table.get_prop_addr(&memory, 2, 5), Ok(0xb6));
+        assert_eq!(table.get_prop_addr(&memory, 2, 4), Ok(0));
+        assert_eq!(table.get_prop_len(&memory, 0xb6), Ok(2));
+        assert_eq!(table.get_next_prop(&memory, 2, 0), Ok(5));
+        assert_eq!(table.get_next_prop(&memory, 2, 5), Ok(2));
+        assert_eq!(table.get_next_prop(&memory, 2, 2), Ok(0));
+        assert_eq!(table.get_next_prop(&memory, 2, 4), Err(ObjectError::MissingProperty { object: 2, property: 4 }));
+
+        // a 3 byte property can not be read as a value
+        memory[0xb5..0xb9].copy_from_slice(&[0x45, 0x12, 0x34, 0x56]);
+        assert_eq!(table.get_prop(&memory, 2, 5), Err(ObjectError::PropertyLength { object: 2, property: 5, length: 3 }));
+    }
+
+    #[test]
+    fn test_object_tree_moves() {
+        let mut memory = story_v3();
+        let table = ObjectTable::new(ZVersion::V3, 0x40);
+
+        table.remove_obj(&mut memory, 3).unwrap();
+        assert_eq!(table.children(&memory, 1), Some(vec![2]));
+        assert_eq!(table.object(&memory, 2).unwrap().sibling, 0);
+        assert_eq!(table.object(&memory, 3).unwrap().parent, 0);
+
+        table.insert_obj(&mut memory, 3, 2).unwrap();
+        table.insert_obj(&mut memory, 3, 1).unwrap();
+        assert_eq!(table.children(&memory, 1), Some(vec![3, 2]));
+        assert_eq!(table.children(&memory, 2), Some(vec![]));
+
+        table.insert_obj(&mut memory, 3, 2).unwrap();
+        assert_eq!(table.insert_obj(&mut memory, 1, 3), Err(ObjectError::TreeLoop { object: 1, destination: 3 }));
+        assert_eq!(table.insert_obj(&mut memory, 2, 256), Err(ObjectError::InvalidObject { object: 256 }));
+        assert_eq!(table.children(&memory, 1), Some(vec![2]));
+        assert_eq!(table.children(&memory, 2), Some(vec![3]));
+    }
+
+    #[test]
+    fn test_object_tree_moves_reject_cycles() {
+        let mut memory = story_v3();
+        let table = ObjectTable::new(ZVersion::V3, 0x40);
+
+        // the lamp is its own sibling
+        let lamp = table.entry_address(2).unwrap();
+        memory[lamp + 5] = 2;
+        assert_eq!(table.remove_obj(&mut memory, 3), Err(ObjectError::CorruptTree { object: 3 }));
+
+        // the room is inside the lamp inside the room
+        memory = story_v3();
+        let room = table.entry_address(1).unwrap();
+        memory[room + 4] = 2;
+        assert_eq!(table.insert_obj(&mut memory, 3, 2), Err(ObjectError::CorruptTree { object: 2 }));
+    }
+}
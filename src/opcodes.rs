@@ -0,0 +1,414 @@
+use crate::zcode::{OperandCount, ZInstruction};
+use crate::zversion::ZVersion;
+
+use OperandCount::{Ext, Op0, Op1, Op2, Var};
+use Mnemonic::*;
+use ZVersion::{V1, V3, V4, V5, V6, V7, V8};
+
+/// Declare `Mnemonic` with one variant per opcode name.
+macro_rules! mnemonics {
+    ($($variant:ident => $name:literal,)*) => {
+        /// The opcodes by meaning, independent of where the
+        /// versions put them.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum Mnemonic {
+            $($variant,)*
+        }
+
+        impl Mnemonic {
+            /// The name the standard uses for the opcode.
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(Mnemonic::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+mnemonics! {
+    Je => "je",
+    Jl => "jl",
+    Jg => "jg",
+    DecChk => "dec_chk",
+    IncChk => "inc_chk",
+    Jin => "jin",
+    Test => "test",
+    Or => "or",
+    And => "and",
+    TestAttr => "test_attr",
+    SetAttr => "set_attr",
+    ClearAttr => "clear_attr",
+    Store => "store",
+    InsertObj => "insert_obj",
+    Loadw => "loadw",
+    Loadb => "loadb",
+    GetProp => "get_prop",
+    GetPropAddr => "get_prop_addr",
+    GetNextProp => "get_next_prop",
+    Add => "add",
+    Sub => "sub",
+    Mul => "mul",
+    Div => "div",
+    Mod => "mod",
+    Call2s => "call_2s",
+    Call2n => "call_2n",
+    SetColour => "set_colour",
+    Throw => "throw",
+    Jz => "jz",
+    GetSibling => "get_sibling",
+    GetChild => "get_child",
+    GetParent => "get_parent",
+    GetPropLen => "get_prop_len",
+    Inc => "inc",
+    Dec => "dec",
+    PrintAddr => "print_addr",
+    Call1s => "call_1s",
+    RemoveObj => "remove_obj",
+    PrintObj => "print_obj",
+    Ret => "ret",
+    Jump => "jump",
+    PrintPaddr => "print_paddr",
+    Load => "load",
+    Not => "not",
+    Call1n => "call_1n",
+    Rtrue => "rtrue",
+    Rfalse => "rfalse",
+    Print => "print",
+    PrintRet => "print_ret",
+    Nop => "nop",
+    Save => "save",
+    Restore => "restore",
+    Restart => "restart",
+    RetPopped => "ret_popped",
+    Pop => "pop",
+    Catch => "catch",
+    Quit => "quit",
+    NewLine => "new_line",
+    ShowStatus => "show_status",
+    Verify => "verify",
+    Piracy => "piracy",
+    Call => "call",
+    CallVs => "call_vs",
+    Storew => "storew",
+    Storeb => "storeb",
+    PutProp => "put_prop",
+    Sread => "sread",
+    Aread => "aread",
+    PrintChar => "print_char",
+    PrintNum => "print_num",
+    Random => "random",
+    Push => "push",
+    Pull => "pull",
+    SplitWindow => "split_window",
+    SetWindow => "set_window",
+    CallVs2 => "call_vs2",
+    EraseWindow => "erase_window",
+    EraseLine => "erase_line",
+    SetCursor => "set_cursor",
+    GetCursor => "get_cursor",
+    SetTextStyle => "set_text_style",
+    BufferMode => "buffer_mode",
+    OutputStream => "output_stream",
+    InputStream => "input_stream",
+    SoundEffect => "sound_effect",
+    ReadChar => "read_char",
+    ScanTable => "scan_table",
+    CallVn => "call_vn",
+    CallVn2 => "call_vn2",
+    Tokenise => "tokenise",
+    EncodeText => "encode_text",
+    CopyTable => "copy_table",
+    PrintTable => "print_table",
+    CheckArgCount => "check_arg_count",
+    LogShift => "log_shift",
+    ArtShift => "art_shift",
+    SetFont => "set_font",
+    DrawPicture => "draw_picture",
+    PictureData => "picture_data",
+    ErasePicture => "erase_picture",
+    SetMargins => "set_margins",
+    SaveUndo => "save_undo",
+    RestoreUndo => "restore_undo",
+    PrintUnicode => "print_unicode",
+    CheckUnicode => "check_unicode",
+    SetTrueColour => "set_true_colour",
+    MoveWindow => "move_window",
+    WindowSize => "window_size",
+    WindowStyle => "window_style",
+    GetWindProp => "get_wind_prop",
+    ScrollWindow => "scroll_window",
+    PopStack => "pop_stack",
+    ReadMouse => "read_mouse",
+    MouseWindow => "mouse_window",
+    PushStack => "push_stack",
+    PutWindProp => "put_wind_prop",
+    PrintForm => "print_form",
+    MakeMenu => "make_menu",
+    PictureTable => "picture_table",
+    BufferScreen => "buffer_screen",
+}
+
+impl Mnemonic {
+    /// Whether the opcode calls the routine given as its
+    /// first operand.
+    pub fn is_call(self) -> bool {
+        matches!(self, Call | CallVs | CallVs2 | CallVn | CallVn2 | Call1s | Call1n | Call2s | Call2n)
+    }
+}
+
+/// What there is to know about an opcode besides its
+/// operands: its mnemonic (as used by the standard), whether
+/// it is followed by a store byte, branch information or an
+/// inline z-string and the versions it is valid in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub mnemonic: Mnemonic,
+    pub name: &'static str,
+    pub operand_count: OperandCount,
+    pub opcode: u8,
+    pub store: bool,
+    pub branch: bool,
+    pub text: bool,
+    pub min_version: ZVersion,
+    pub max_version: ZVersion,
+}
+
+impl OpcodeInfo {
+    pub fn is_valid_in(&self, version: ZVersion) -> bool {
+        self.min_version <= version && version <= self.max_version
+    }
+}
+
+// flags of the table below: store, branch, text
+const N: u8 = 0;
+const S: u8 = 1;
+const B: u8 = 2;
+const T: u8 = 4;
+
+const fn op(
+    operand_count: OperandCount,
+    opcode: u8,
+    mnemonic: Mnemonic,
+    flags: u8,
+    min_version: ZVersion,
+    max_version: ZVersion,
+) -> OpcodeInfo {
+    OpcodeInfo {
+        mnemonic,
+        name: mnemonic.name(),
+        operand_count,
+        opcode,
+        store: flags & S != 0,
+        branch: flags & B != 0,
+        text: flags & T != 0,
+        min_version,
+        max_version,
+    }
+}
+
+/// All opcodes of versions 1 to 8. Opcodes whose meaning
+/// changed between versions have one entry per meaning.
+pub const OPCODES: &[OpcodeInfo] = &[
+    op(Op2, 1, Je, B, V1, V8),
+    op(Op2, 2, Jl, B, V1, V8),
+    op(Op2, 3, Jg, B, V1, V8),
+    op(Op2, 4, DecChk, B, V1, V8),
+    op(Op2, 5, IncChk, B, V1, V8),
+    op(Op2, 6, Jin, B, V1, V8),
+    op(Op2, 7, Test, B, V1, V8),
+    op(Op2, 8, Or, S, V1, V8),
+    op(Op2, 9, And, S, V1, V8),
+    op(Op2, 10, TestAttr, B, V1, V8),
+    op(Op2, 11, SetAttr, N, V1, V8),
+    op(Op2, 12, ClearAttr, N, V1, V8),
+    op(Op2, 13, Store, N, V1, V8),
+    op(Op2, 14, InsertObj, N, V1, V8),
+    op(Op2, 15, Loadw, S, V1, V8),
+    op(Op2, 16, Loadb, S, V1, V8),
+    op(Op2, 17, GetProp, S, V1, V8),
+    op(Op2, 18, GetPropAddr, S, V1, V8),
+    op(Op2, 19, GetNextProp, S, V1, V8),
+    op(Op2, 20, Add, S, V1, V8),
+    op(Op2, 21, Sub, S, V1, V8),
+    op(Op2, 22, Mul, S, V1, V8),
+    op(Op2, 23, Div, S, V1, V8),
+    op(Op2, 24, Mod, S, V1, V8),
+    op(Op2, 25, Call2s, S, V4, V8),
+    op(Op2, 26, Call2n, N, V5, V8),
+    op(Op2, 27, SetColour, N, V5, V8),
+    op(Op2, 28, Throw, N, V5, V8),
+
+    op(Op1, 0, Jz, B, V1, V8),
+    op(Op1, 1, GetSibling, S | B, V1, V8),
+    op(Op1, 2, GetChild, S | B, V1, V8),
+    op(Op1, 3, GetParent, S, V1, V8),
+    op(Op1, 4, GetPropLen, S, V1, V8),
+    op(Op1, 5, Inc, N, V1, V8),
+    op(Op1, 6, Dec, N, V1, V8),
+    op(Op1, 7, PrintAddr, N, V1, V8),
+    op(Op1, 8, Call1s, S, V4, V8),
+    op(Op1, 9, RemoveObj, N, V1, V8),
+    op(Op1, 10, PrintObj, N, V1, V8),
+    op(Op1, 11, Ret, N, V1, V8),
+    op(Op1, 12, Jump, N, V1, V8),
+    op(Op1, 13, PrintPaddr, N, V1, V8),
+    op(Op1, 14, Load, S, V1, V8),
+    op(Op1, 15, Not, S, V1, V4),
+    op(Op1, 15, Call1n, N, V5, V8),
+
+    op(Op0, 0, Rtrue, N, V1, V8),
+    op(Op0, 1, Rfalse, N, V1, V8),
+    op(Op0, 2, Print, T, V1, V8),
+    op(Op0, 3, PrintRet, T, V1, V8),
+    op(Op0, 4, Nop, N, V1, V8),
+    op(Op0, 5, Save, B, V1, V3),
+    op(Op0, 5, Save, S, V4, V4),
+    op(Op0, 6, Restore, B, V1, V3),
+    op(Op0, 6, Restore, S, V4, V4),
+    op(Op0, 7, Restart, N, V1, V8),
+    op(Op0, 8, RetPopped, N, V1, V8),
+    op(Op0, 9, Pop, N, V1, V4),
+    op(Op0, 9, Catch, S, V5, V8),
+    op(Op0, 10, Quit, N, V1, V8),
+    op(Op0, 11, NewLine, N, V1, V8),
+    op(Op0, 12, ShowStatus, N, V3, V3),
+    op(Op0, 13, Verify, B, V3, V8),
+    op(Op0, 15, Piracy, B, V5, V8),
+
+    op(Var, 0, Call, S, V1, V3),
+    op(Var, 0, CallVs, S, V4, V8),
+    op(Var, 1, Storew, N, V1, V8),
+    op(Var, 2, Storeb, N, V1, V8),
+    op(Var, 3, PutProp, N, V1, V8),
+    op(Var, 4, Sread, N, V1, V4),
+    op(Var, 4, Aread, S, V5, V8),
+    op(Var, 5, PrintChar, N, V1, V8),
+    op(Var, 6, PrintNum, N, V1, V8),
+    op(Var, 7, Random, S, V1, V8),
+    op(Var, 8, Push, N, V1, V8),
+    op(Var, 9, Pull, N, V1, V5),
+    op(Var, 9, Pull, S, V6, V6),
+    op(Var, 9, Pull, N, V7, V8),
+    op(Var, 10, SplitWindow, N, V3, V8),
+    op(Var, 11, SetWindow, N, V3, V8),
+    op(Var, 12, CallVs2, S, V4, V8),
+    op(Var, 13, EraseWindow, N, V4, V8),
+    op(Var, 14, EraseLine, N, V4, V8),
+    op(Var, 15, SetCursor, N, V4, V8),
+    op(Var, 16, GetCursor, N, V4, V8),
+    op(Var, 17, SetTextStyle, N, V4, V8),
+    op(Var, 18, BufferMode, N, V4, V8),
+    op(Var, 19, OutputStream, N, V3, V8),
+    op(Var, 20, InputStream, N, V3, V8),
+    op(Var, 21, SoundEffect, N, V3, V8),
+    op(Var, 22, ReadChar, S, V4, V8),
+    op(Var, 23, ScanTable, S | B, V4, V8),
+    op(Var, 24, Not, S, V5, V8),
+    op(Var, 25, CallVn, N, V5, V8),
+    op(Var, 26, CallVn2, N, V5, V8),
+    op(Var, 27, Tokenise, N, V5, V8),
+    op(Var, 28, EncodeText, N, V5, V8),
+    op(Var, 29, CopyTable, N, V5, V8),
+    op(Var, 30, PrintTable, N, V5, V8),
+    op(Var, 31, CheckArgCount, B, V5, V8),
+
+    op(Ext, 0, Save, S, V5, V8),
+    op(Ext, 1, Restore, S, V5, V8),
+    op(Ext, 2, LogShift, S, V5, V8),
+    op(Ext, 3, ArtShift, S, V5, V8),
+    op(Ext, 4, SetFont, S, V5, V8),
+    op(Ext, 5, DrawPicture, N, V6, V6),
+    op(Ext, 6, PictureData, B, V6, V6),
+    op(Ext, 7, ErasePicture, N, V6, V6),
+    op(Ext, 8, SetMargins, N, V6, V6),
+    op(Ext, 9, SaveUndo, S, V5, V8),
+    op(Ext, 10, RestoreUndo, S, V5, V8),
+    op(Ext, 11, PrintUnicode, N, V5, V8),
+    op(Ext, 12, CheckUnicode, S, V5, V8),
+    op(Ext, 13, SetTrueColour, N, V5, V8),
+    op(Ext, 16, MoveWindow, N, V6, V6),
+    op(Ext, 17, WindowSize, N, V6, V6),
+    op(Ext, 18, WindowStyle, N, V6, V6),
+    op(Ext, 19, GetWindProp, S, V6, V6),
+    op(Ext, 20, ScrollWindow, N, V6, V6),
+    op(Ext, 21, PopStack, N, V6, V6),
+    op(Ext, 22, ReadMouse, N, V6, V6),
+    op(Ext, 23, MouseWindow, N, V6, V6),
+    op(Ext, 24, PushStack, B, V6, V6),
+    op(Ext, 25, PutWindProp, N, V6, V6),
+    op(Ext, 26, PrintForm, N, V6, V6),
+    op(Ext, 27, MakeMenu, B, V6, V6),
+    op(Ext, 28, PictureTable, N, V6, V6),
+    op(Ext, 29, BufferScreen, S, V6, V6),
+];
+
+/// Look up the opcode `opcode` of class `operand_count`
+/// as understood by `version`.
+pub fn lookup(
+    operand_count: OperandCount,
+    opcode: u8,
+    version: ZVersion
+) -> Option<&'static OpcodeInfo> {
+    OPCODES.iter().find(|info| {
+        info.operand_count == operand_count
+            && info.opcode == opcode
+            && info.is_valid_in(version)
+    })
+}
+
+impl ZInstruction {
+    /// Metadata of the instruction's opcode in `version`.
+    pub fn info(&self, version: ZVersion) -> Option<&'static OpcodeInfo> {
+        lookup(self.operand_count(), self.opcode(), version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, OPCODES};
+    use crate::zcode::{decode_instruction, OperandCount};
+    use crate::zversion::ZVersion;
+
+    #[test]
+    fn test_opcodes_are_unique_per_version() {
+        for n in 1..=8 {
+            let version = ZVersion::from_u8(n).unwrap();
+            for (i, a) in OPCODES.iter().enumerate() {
+                for b in OPCODES[i + 1..].iter() {
+                    assert!(!(a.operand_count == b.operand_count
+                        && a.opcode == b.opcode
+                        && a.is_valid_in(version)
+                        && b.is_valid_in(version)), "{} and {} clash", a.name, b.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lookup_resolves_version_dependent_opcodes() {
+        // 1OP:143
+        let memory = vec![0x8f, 0x12, 0x34, 0x00];
+        for n in 1..=4 {
+            let version = ZVersion::from_u8(n).unwrap();
+            let instruction = decode_instruction(&memory, 0, version).unwrap();
+            assert_eq!(instruction.info(version).unwrap().name, "not");
+        }
+        for n in 5..=8 {
+            let version = ZVersion::from_u8(n).unwrap();
+            let instruction = decode_instruction(&memory, 0, version).unwrap();
+            assert_eq!(instruction.info(version).unwrap().name, "call_1n");
+        }
+
+        let info = lookup(OperandCount::Op0, 12, ZVersion::V3).unwrap();
+        assert_eq!(info.name, "show_status");
+        assert_eq!(lookup(OperandCount::Op0, 12, ZVersion::V4), None);
+        assert_eq!(lookup(OperandCount::Op0, 14, ZVersion::V5), None);
+        assert_eq!(lookup(OperandCount::Ext, 5, ZVersion::V5), None);
+
+        let info = lookup(OperandCount::Var, 23, ZVersion::V5).unwrap();
+        assert!(info.store && info.branch && !info.text);
+        let info = lookup(OperandCount::Op0, 3, ZVersion::V1).unwrap();
+        assert!(!info.store && !info.branch && info.text);
+    }
+}
@@ -0,0 +1,280 @@
+use std::fmt;
+
+use crate::util::{read_word, write_word};
+use crate::zscii::ZsciiTable;
+
+/// Receiver of the text shown on screen (output stream 1).
+pub trait Screen {
+    fn print(&mut self, text: &str);
+}
+
+/// Collects the screen output, handy for tools and tests.
+impl Screen for String {
+    fn print(&mut self, text: &str) {
+        self.push_str(text);
+    }
+}
+
+/// Errors from selecting output streams.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StreamError {
+    /// no output stream with this number
+    InvalidStream { stream: i16 },
+    /// stream 3 selected more than 16 times
+    TooManyTables,
+    /// stream 3 selected without a table address
+    NoTable,
+    /// the stream 3 table lies outside of dynamic memory
+    TableOutOfRange { address: usize },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::InvalidStream { stream } => {
+                write!(f, "invalid output stream {}", stream)
+            },
+            StreamError::TooManyTables => {
+                write!(f, "output stream 3 nested too deeply")
+            },
+            StreamError::NoTable => {
+                write!(f, "output stream 3 selected without a table")
+            },
+            StreamError::TableOutOfRange { address } => {
+                write!(f, "output stream 3 table at {:#06x} lies outside dynamic memory", address)
+            },
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+/// Stream 3 may be selected up to 16 times without being
+/// deselected in between.
+const MAX_TABLES: usize = 16;
+
+/// Flags 2, of which bit 0 turns the transcript on, is the
+/// word at 0x10 of the header.
+pub(crate) const FLAGS2: usize = 0x10;
+
+/// The output streams of the machine: the screen (1), the
+/// transcript (2), memory tables (3) and the record of the
+/// player's commands (4). Whether the transcript is on is
+/// kept in the story's Flags 2, as the story may toggle it
+/// directly, so printing needs the story's memory.
+///
+/// Text sent to stream 3 goes nowhere else. A table starts
+/// with a word holding the number of zscii characters
+/// written to it so far, and must fit into dynamic memory.
+pub struct OutputStreams<S: Screen> {
+    pub screen: S,
+    /// whether stream 1 is selected
+    pub screen_selected: bool,
+    pub transcript: String,
+    pub commands: String,
+    /// whether stream 4 is selected
+    pub commands_selected: bool,
+    /// addresses of the selected stream 3 tables, the last
+    /// one receives the text
+    tables: Vec<usize>,
+    /// end of dynamic memory (the static memory base)
+    dynamic: usize,
+}
+
+impl<S: Screen> OutputStreams<S> {
+    /// Streams for a story whose static memory starts at
+    /// `dynamic`.
+    pub fn new(screen: S, dynamic: usize) -> OutputStreams<S> {
+        OutputStreams {
+            screen,
+            screen_selected: true,
+            transcript: String::new(),
+            commands: String::new(),
+            commands_selected: false,
+            tables: Vec::new(),
+            dynamic,
+        }
+    }
+
+    pub fn transcript_selected(&self, memory: &[u8]) -> bool {
+        read_word(memory, FLAGS2).is_some_and(|flags| flags & 1 != 0)
+    }
+
+    /// Address of the stream 3 table receiving text, if any.
+    pub fn table(&self) -> Option<usize> {
+        self.tables.last().copied()
+    }
+
+    /// Select (positive `stream`) or deselect (negative) an
+    /// output stream as `output_stream` does. Selecting
+    /// stream 3 needs the address of the table.
+    pub fn select(&mut self, memory: &mut [u8], stream: i16, table: Option<usize>) -> Result<(), StreamError> {
+        match stream {
+            1 | -1 => self.screen_selected = stream > 0,
+            2 | -2 => {
+                let flags = read_word(memory, FLAGS2).ok_or(StreamError::TableOutOfRange { address: FLAGS2 })?;
+                let flags = if stream > 0 { flags | 1 } else { flags & !1 };
+                write_word(memory, FLAGS2, flags).ok_or(StreamError::TableOutOfRange { address: FLAGS2 })?;
+            },
+            3 => {
+                if self.tables.len() == MAX_TABLES {
+                    return Err(StreamError::TooManyTables);
+                }
+                let address = table.ok_or(StreamError::NoTable)?;
+                write_word(self.writable(memory), address, 0).ok_or(StreamError::TableOutOfRange { address })?;
+                self.tables.push(address);
+            },
+            -3 => {
+                self.tables.pop();
+            },
+            4 | -4 => self.commands_selected = stream > 0,
+            _ => return Err(StreamError::InvalidStream { stream }),
+        }
+        Ok(())
+    }
+
+    /// The part of `memory` tables may be written to.
+    fn writable<'a>(&self, memory: &'a mut [u8]) -> &'a mut [u8] {
+        let end = self.dynamic.min(memory.len());
+        &mut memory[..end]
+    }
+
+    /// Append zscii `code` to the current stream 3 table.
+    fn write_table(&mut self, memory: &mut [u8], table: usize, code: u8) -> Result<(), StreamError> {
+        let out_of_range = StreamError::TableOutOfRange { address: table };
+        let memory = self.writable(memory);
+        let length = read_word(memory, table).ok_or(out_of_range)?;
+        *memory.get_mut(table + 2 + length as usize).ok_or(out_of_range)? = code;
+        write_word(memory, table, length.wrapping_add(1)).ok_or(out_of_range)
+    }
+
+    /// Print zscii `code` (as by `print_char`), converted to
+    /// text with `zscii`.
+    pub fn print_zscii(
+        &mut self,
+        memory: &mut [u8],
+        zscii: &ZsciiTable,
+        code: u16
+    ) -> Result<(), StreamError> {
+        if let Some(table) = self.table() {
+            return self.write_table(memory, table, code as u8);
+        }
+        match code {
+            13 => self.send(memory, "\n"),
+            _ => if let Some(c) = zscii.to_char(code) {
+                self.send(memory, c.encode_utf8(&mut [0; 4]));
+            },
+        }
+        Ok(())
+    }
+
+    /// Print `text` as produced by the text decoder. Stream 3
+    /// receives it converted back to zscii with `zscii`,
+    /// dropping characters without a zscii code.
+    pub fn print(&mut self, memory: &mut [u8], zscii: &ZsciiTable, text: &str) -> Result<(), StreamError> {
+        if let Some(table) = self.table() {
+            for c in text.chars() {
+                let code = match c {
+                    '\n' => Some(13),
+                    _ => zscii.to_zscii(c),
+                };
+                if let Some(code) = code {
+                    self.write_table(memory, table, code as u8)?;
+                }
+            }
+            return Ok(());
+        }
+        self.send(memory, text);
+        Ok(())
+    }
+
+    /// Send `text` to the screen and transcript as selected.
+    fn send(&mut self, memory: &[u8], text: &str) {
+        if self.screen_selected {
+            self.screen.print(text);
+        }
+        if self.transcript_selected(memory) {
+            self.transcript.push_str(text);
+        }
+    }
+
+    /// Record a command typed by the player (stream 4).
+    pub fn record_command(&mut self, command: &str) {
+        if self.commands_selected {
+            self.commands.push_str(command);
+            self.commands.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OutputStreams, StreamError};
+    use crate::zscii::ZsciiTable;
+
+    #[test]
+    fn test_screen_and_transcript() {
+        let mut memory = vec![0u8; 0x100];
+        let zscii = ZsciiTable::default();
+        let mut streams = OutputStreams::new(String::new(), 0xc0);
+
+        streams.print(&mut memory, &zscii, "West of House\n").unwrap();
+        streams.select(&mut memory, 2, None).unwrap();
+        assert_eq!(memory[0x11], 1);
+        streams.print_zscii(&mut memory, &zscii, 'A' as u16).unwrap();
+        streams.print_zscii(&mut memory, &zscii, 13).unwrap();
+        streams.select(&mut memory, -1, None).unwrap();
+        streams.print(&mut memory, &zscii, "only transcript").unwrap();
+        // the story turns the transcript off itself
+        memory[0x11] = 0;
+        streams.print(&mut memory, &zscii, "nowhere").unwrap();
+
+        assert_eq!(streams.screen, "West of House\nA\n");
+        assert_eq!(streams.transcript, "A\nonly transcript");
+        assert_eq!(streams.select(&mut memory, 5, None), Err(StreamError::InvalidStream { stream: 5 }));
+    }
+
+    #[test]
+    fn test_memory_tables_nest() {
+        let mut memory = vec![0u8; 0x100];
+        let zscii = ZsciiTable::default();
+        let mut streams = OutputStreams::new(String::new(), 0xc0);
+
+        streams.select(&mut memory, 3, Some(0x40)).unwrap();
+        streams.print(&mut memory, &zscii, "ab").unwrap();
+        streams.select(&mut memory, 3, Some(0x80)).unwrap();
+        streams.print(&mut memory, &zscii, "c\n").unwrap();
+        streams.select(&mut memory, -3, None).unwrap();
+        streams.print_zscii(&mut memory, &zscii, 'd' as u16).unwrap();
+        streams.select(&mut memory, -3, None).unwrap();
+        streams.print(&mut memory, &zscii, "e").unwrap();
+
+        assert_eq!(&memory[0x40..0x45], &[0, 3, b'a', b'b', b'd']);
+        assert_eq!(&memory[0x80..0x84], &[0, 2, b'c', 13]);
+        assert_eq!(streams.screen, "e");
+
+        for _ in 0..16 {
+            streams.select(&mut memory, 3, Some(0x40)).unwrap();
+        }
+        assert_eq!(streams.select(&mut memory, 3, Some(0x40)), Err(StreamError::TooManyTables));
+        assert_eq!(streams.select(&mut memory, -3, None), Ok(()));
+        assert_eq!(streams.select(&mut memory, 3, Some(0xbf)), Err(StreamError::TableOutOfRange { address: 0xbf }));
+        assert_eq!(streams.select(&mut memory, 3, None), Err(StreamError::NoTable));
+
+        // text running past dynamic memory
+        streams.select(&mut memory, 3, Some(0xbd)).unwrap();
+        streams.print(&mut memory, &zscii, "f").unwrap();
+        assert_eq!(streams.print(&mut memory, &zscii, "g"), Err(StreamError::TableOutOfRange { address: 0xbd }));
+        assert_eq!(&memory[0xbd..0xc1], &[0, 1, b'f', 0]);
+    }
+
+    #[test]
+    fn test_command_record() {
+        let mut memory = vec![0u8; 0x40];
+        let mut streams = OutputStreams::new(String::new(), 0xc0);
+        streams.record_command("open mailbox");
+        streams.select(&mut memory, 4, None).unwrap();
+        streams.record_command("read leaflet");
+        assert_eq!(streams.commands, "read leaflet\n");
+    }
+}
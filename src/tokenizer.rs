@@ -0,0 +1,184 @@
+use crate::dictionary::Dictionary;
+use crate::util::ZCodec;
+use crate::zversion::ZVersion;
+
+/// A word found in the text buffer: where it starts (as byte
+/// offset from the start of the text buffer, which is what
+/// the parse buffer records), its length and the address of
+/// its dictionary entry (0 for unknown words).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub position: usize,
+    pub length: usize,
+    pub entry: usize,
+}
+
+/// The typed text in the text buffer at `address`. Versions
+/// 1-4 store the text from byte 1 up to a terminating 0,
+/// later versions store the number of characters in byte 1
+/// and the text from byte 2. Returns the offset of the text
+/// in the buffer and the text itself.
+pub fn read_text(memory: &[u8], address: usize, version: ZVersion) -> Option<(usize, &[u8])> {
+    let max = *memory.get(address)? as usize;
+    if version <= ZVersion::V4 {
+        let text = memory.get(address + 1..)?;
+        let length = text.iter().take(max).position(|&b| b == 0).unwrap_or(max.min(text.len()));
+        return Some((1, &text[..length]));
+    }
+    let length = (*memory.get(address + 1)? as usize).min(max);
+    Some((2, memory.get(address + 2..address + 2 + length)?))
+}
+
+/// Split `text` into words: spaces end words, and each word
+/// separator of the dictionary is a word on its own. Returns
+/// offset and length of each word.
+pub fn split(text: &[u8], dictionary: &Dictionary) -> Vec<(usize, usize)> {
+    let mut words: Vec<(usize, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, &b) in text.iter().enumerate() {
+        if b == b' ' || dictionary.is_separator(b) {
+            if let Some(s) = start.take() {
+                words.push((s, i - s));
+            }
+            if b != b' ' {
+                words.push((i, 1));
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len() - s));
+    }
+    words
+}
+
+/// Look up the words of `text` (zscii) in `dictionary`. Words
+/// are encoded like dictionary entries, so only their first 6
+/// (since version 4: 9) z-chars are significant.
+pub fn tokens(text: &[u8], dictionary: &Dictionary, memory: &[u8], codec: &ZCodec) -> Vec<Token> {
+    split(text, dictionary)
+        .into_iter()
+        .map(|(position, length)| {
+            let word: String = text[position..position + length]
+                .iter()
+                .filter_map(|&b| codec.zscii.to_char(b as u16))
+                .collect();
+            let entry = dictionary.lookup(memory, &word, codec).unwrap_or(0);
+            Token { position, length, entry }
+        })
+        .collect()
+}
+
+/// Lexical analysis as done by `sread`/`aread` and `tokenise`:
+/// split the text buffer at `text`, look each word up in
+/// `dictionary` (the story's own or a custom one) and fill in
+/// the parse buffer at `parse`. Byte 0 of the parse buffer
+/// limits the number of words, byte 1 receives the number of
+/// words and each word gets a 4 byte block holding the entry
+/// address, the word length and its position in the text
+/// buffer. With `skip_unknown` set the blocks of unknown
+/// words are left as they are (used by games to fill in
+/// their own vocabulary in a second pass).
+pub fn tokenise(
+    memory: &mut [u8],
+    text: usize,
+    parse: usize,
+    dictionary: &Dictionary,
+    codec: &ZCodec,
+    skip_unknown: bool
+) -> Option<Vec<Token>> {
+    let (offset, input) = read_text(memory, text, codec.version)?;
+    let max = *memory.get(parse)? as usize;
+    let mut tokens = tokens(input, dictionary, memory, codec);
+    tokens.truncate(max);
+    for token in tokens.iter_mut() {
+        token.position += offset;
+    }
+
+    *memory.get_mut(parse + 1)? = tokens.len() as u8;
+    for (i, token) in tokens.iter().enumerate() {
+        if skip_unknown && token.entry == 0 {
+            continue;
+        }
+        let block = memory.get_mut(parse + 2 + 4 * i..parse + 6 + 4 * i)?;
+        block[..2].copy_from_slice(&(token.entry as u16).to_be_bytes());
+        block[2] = token.length as u8;
+        block[3] = token.position as u8;
+    }
+    Some(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, tokenise, Token};
+    use crate::dictionary::{self, Dictionary};
+    use crate::util::ZCodec;
+    use crate::zversion::ZVersion;
+
+    /// Dictionary at 0x10 holding `words` (in sorted order)
+    /// with separators '.' and ','.
+    fn story(version: ZVersion, words: &[&str]) -> (Vec<u8>, Dictionary) {
+        let codec = ZCodec::new(version);
+        let mut words = words.to_vec();
+        words.sort_by_cached_key(|w| codec.encode_dictionary_word(w));
+        let mut memory = dictionary::tests::story(version, &words, &[], words.len() as i16);
+        memory.resize(0x100, 0);
+        let dictionary = Dictionary::parse(&memory, 0x10, version).unwrap();
+        (memory, dictionary)
+    }
+
+    #[test]
+    fn test_split_on_spaces_and_separators() {
+        let (_, dictionary) = story(ZVersion::V3, &["take"]);
+        assert_eq!(split(b"take lamp", &dictionary), vec![(0, 4), (5, 4)]);
+        assert_eq!(split(b"  go north,east.", &dictionary),
+            vec![(2, 2), (5, 5), (10, 1), (11, 4), (15, 1)]);
+        assert_eq!(split(b"   ", &dictionary), vec![]);
+    }
+
+    #[test]
+    fn test_tokenise_v3_text_buffer() {
+        let (mut memory, dictionary) = story(ZVersion::V3, &["take", "lantern", ","]);
+        let codec = ZCodec::new(ZVersion::V3);
+        let take = dictionary.lookup(&memory, "take", &codec).unwrap();
+        let lantern = dictionary.lookup(&memory, "lantern", &codec).unwrap();
+        let comma = dictionary.lookup(&memory, ",", &codec).unwrap();
+
+        memory[0x80] = 30;
+        memory[0x81..0x81 + 22].copy_from_slice(b"take lanterns,xyzzy a\0");
+        memory[0xc0] = 3;
+        let tokens = tokenise(&mut memory, 0x80, 0xc0, &dictionary, &codec, false).unwrap();
+        assert_eq!(tokens, vec![
+            Token { position: 1, length: 4, entry: take },
+            Token { position: 6, length: 8, entry: lantern },
+            Token { position: 14, length: 1, entry: comma },
+        ]);
+        assert_eq!(memory[0xc1], 3);
+        assert_eq!(&memory[0xc2..0xce], &[
+            (take >> 8) as u8, take as u8, 4, 1,
+            (lantern >> 8) as u8, lantern as u8, 8, 6,
+            (comma >> 8) as u8, comma as u8, 1, 14,
+        ]);
+    }
+
+    #[test]
+    fn test_tokenise_v5_skips_unknown_words() {
+        let (mut memory, dictionary) = story(ZVersion::V5, &["open", "mailbox"]);
+        let codec = ZCodec::new(ZVersion::V5);
+        let open = dictionary.lookup(&memory, "open", &codec).unwrap();
+
+        memory[0x80] = 20;
+        memory[0x81] = 14;
+        memory[0x82..0x82 + 14].copy_from_slice(b"open the chest");
+        memory[0xc0] = 10;
+        memory[0xc2..0xce].copy_from_slice(&[0xaa; 12]);
+        tokenise(&mut memory, 0x80, 0xc0, &dictionary, &codec, true).unwrap();
+        assert_eq!(memory[0xc1], 3);
+        assert_eq!(&memory[0xc2..0xc6], &[(open >> 8) as u8, open as u8, 4, 2]);
+        assert_eq!(&memory[0xc6..0xce], &[0xaa; 8]);
+
+        tokenise(&mut memory, 0x80, 0xc0, &dictionary, &codec, false).unwrap();
+        assert_eq!(&memory[0xc6..0xce], &[0, 0, 3, 7, 0, 0, 5, 11]);
+    }
+}
@@ -1,4 +1,12 @@
-use crate::zscii::{A0, A1, A2, ZSCIITAB};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::Peekable;
+
+use crate::zscii::{A0, A1, A2, A2_V1, ZsciiTable};
+use crate::zversion::ZVersion;
 
 
 /// `zpack` takes a series of 5bit values (stored
@@ -7,6 +15,7 @@ use crate::zscii::{A0, A1, A2, ZSCIITAB};
 /// z-machine specification. The result is
 /// represented as `Vec<u8>`. The routine will
 /// discard bits 4,6 and 7 of each input byte.
+#[allow(clippy::needless_return)]
 pub fn zpack(input: Vec<u8>) -> Vec<u8> {
     let mut v: Vec<u8> = Vec::new();
 
@@ -31,13 +40,13 @@ pub fn zpack(input: Vec<u8>) -> Vec<u8> {
         v.push(lb);
     }
 
-    if v.len() == 0 {
+    if v.is_empty() {
         return v;
     }
 
     // mark last
     let n = v.len() - 2;
-    let mut second_last = v[n].clone();
+    let mut second_last = v[n];
     second_last += 0x80;
     v[n] = second_last;
     return v;
@@ -48,6 +57,7 @@ pub fn zpack(input: Vec<u8>) -> Vec<u8> {
 /// 3 5bit values according to z-machine specification.
 /// The resulting series of 5bit values are represented
 /// as `Vec<u8>`.
+#[allow(clippy::needless_return)]
 pub fn zunpack(input: Vec<u8>) -> Vec<u8> {
     let mut v: Vec<u8> = Vec::new();
     
@@ -77,72 +87,57 @@ pub fn zunpack(input: Vec<u8>) -> Vec<u8> {
 /// Not that zchars are 5bit unsigned integers. However, the
 /// single zchar is represented as 8bit unsigned. The mapping
 /// is done according to input alphabets `a0`, `a1` and `a2`
-/// as well as to the zscii table `zscii` (specified
-/// by the z-machine standard) .
+/// as well as to the zscii table `zscii` (the default one
+/// specified by the z-machine standard or a story's own
+/// unicode translation table).
 pub fn zmap(
     c: char,
     a0: Vec<char>,
     a1: Vec<char>,
     a2: Vec<char>,
-    zscii: Vec<Option<char>>
+    zscii: &ZsciiTable
 ) -> Option<Vec<u8>> {
-    
-    let mut v: Vec<u8> = Vec::new();
+    let (zchars, n) = zmap_zchars(c, &a0, &a1, &a2, zscii)?;
+    Some(zchars[..n].to_vec())
+}
 
-    // check if is space
+/// Same as `zmap` without allocating: returns up to four
+/// z-chars and the number of z-chars actually used.
+fn zmap_zchars(
+    c: char,
+    a0: &[char],
+    a1: &[char],
+    a2: &[char],
+    zscii: &ZsciiTable
+) -> Option<([u8; 4], usize)> {
     if c == ' ' {
-        v.push(0 as u8);
-        return Some(v);
+        return Some(([0, 0, 0, 0], 1));
     }
-    
-    // check if is A0 char
-    if a0.contains(&c) {
-        let index = a0.iter().position(|&x| x == c).unwrap();
-        v.push(index as u8 + 6);
-        return Some(v);
+    if let Some(index) = a0.iter().position(|&x| x == c) {
+        return Some(([index as u8 + 6, 0, 0, 0], 1));
     }
-
-    // check if is A1 char
-    if a1.contains(&c) {
-        let index = a1.iter().position(|&x| x == c).unwrap();
-        v.push(0x04);
-        v.push(index as u8 + 6);
-        return Some(v);
+    if let Some(index) = a1.iter().position(|&x| x == c) {
+        return Some(([0x04, index as u8 + 6, 0, 0], 2));
     }
-
-    // check if is A2 char
-    if a2.contains(&c) {
-        let index = a2.iter().position(|&x| x == c).unwrap();
-        v.push(0x05);
-        v.push(index as u8 + 7);
-        return Some(v);
+    if let Some(index) = a2.iter().position(|&x| x == c) {
+        return Some(([0x05, index as u8 + 7, 0, 0], 2));
     }
 
     // if the char is not representable by A0, A1 or A2
-    // then map according to ZSCIITAB
-    match zscii.into_iter().position(|x| Some(c) == x) {
-        Some(index) => {
-            v.push(0x05);
-            v.push(0x06);
-            let hb = ((index & 0xe0) >> 5) as u8;
-            let lb = (index & 0x1f) as u8;
-            v.push(hb);
-            v.push(lb);
-            return Some(v)
-        },
-        None => {return None;},
-    };
-
+    // then map according to the zscii table
+    let code = zscii.to_zscii(c)?;
+    Some(([0x05, 0x06, ((code >> 5) & 0x1f) as u8, (code & 0x1f) as u8], 4))
 }
 
+#[allow(clippy::needless_return)]
 pub fn zunmap(
     zchars: Vec<u8>,
     a0: Vec<char>,
     a1: Vec<char>,
     a2: Vec<char>,
-    zscii: Vec<Option<char>>
+    zscii: &ZsciiTable
 ) -> Option<(char, u8)> {
-    if zchars.len() < 1 {
+    if zchars.is_empty() {
         return None;
     } 
 
@@ -151,18 +146,12 @@ pub fn zunmap(
         return Some((' ', 1))
     }
 
-    // newline character in version 1
-    // abbreviation character since version 3
-    else if zchars[0] == 1 {
-        // TODO
-        return None;
-    }
-    
-    // shift lock chars unrecognized
-    // since version  3 recognized
-    // as abbreviation characters
-    else if zchars[0] == 2 || zchars[0] == 3 {
-        // TODO
+    // abbreviation characters since version 3 (z-char 1
+    // is a newline in version 1, z-chars 2 and 3 are shift
+    // characters in versions 1 and 2). An abbreviation
+    // expands to a whole string rather than a single char,
+    // see `abbreviation_index` and `ZCodec::decode`.
+    else if zchars[0] >= 1 && zchars[0] <= 3 {
         return None;
     }
     
@@ -194,7 +183,7 @@ pub fn zunmap(
             let hb = ((zchars[2] & 0x1f) as u16) << 5;
             let lb = (zchars[3] & 0x1f) as u16;
             let index = hb | lb;
-            match zscii[index as usize] {
+            match zscii.to_char(index) {
                 Some(c) => {return Some((c, 4))},
                 None => {return None;}
             }
@@ -225,49 +214,872 @@ pub fn zunmap(
 
 }
 
+/// Text codec of a particular story file. It knows the
+/// version dependent meaning of z-chars 1 to 5 as well as
+/// the alphabets and zscii table in use:
+///
+/// * version 1: z-char 1 is a newline, 2 and 3 shift the
+///   alphabet for the next char, 4 and 5 lock the shift
+/// * version 2: like version 1, but z-char 1 refers to one
+///   of 32 abbreviations
+/// * version 3+: z-chars 1, 2 and 3 refer to one of 96
+///   abbreviations, 4 and 5 shift to A1 and A2 for the
+///   next char, there are no shift locks
+///
+/// In all versions A2 position 6 is the zscii escape and,
+/// since version 2, A2 position 7 is a newline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZCodec {
+    pub version: ZVersion,
+    pub a0: Vec<char>,
+    pub a1: Vec<char>,
+    pub a2: Vec<char>,
+    pub zscii: ZsciiTable,
+}
+
+impl ZCodec {
+    /// Codec using the default alphabets of `version`.
+    pub fn new(version: ZVersion) -> ZCodec {
+        let a2 = match version {
+            ZVersion::V1 => A2_V1.to_vec(),
+            _ => {
+                let mut a2 = A2.to_vec();
+                a2[0] = '\n';
+                a2
+            }
+        };
+        ZCodec {
+            version,
+            a0: A0.to_vec(),
+            a1: A1.to_vec(),
+            a2,
+            zscii: ZsciiTable::default(),
+        }
+    }
+
+    /// Codec for the story file in `memory`. Since version 5
+    /// a story may specify its own unicode translation table
+    /// (see `ZsciiTable::from_story`) and alphabet table. The
+    /// latter's address is found at header offset 0x34 (0 means
+    /// default alphabets).
+    pub fn from_story(memory: &[u8]) -> Option<ZCodec> {
+        let codec = ZCodec {
+            zscii: ZsciiTable::from_story(memory)?,
+            ..ZCodec::new(ZVersion::from_u8(*memory.first()?)?)
+        };
+        if codec.version < ZVersion::V5 {
+            return Some(codec);
+        }
+        let address = ((*memory.get(0x34)? as usize) << 8) | *memory.get(0x35)? as usize;
+        if address == 0 {
+            return Some(codec);
+        }
+        codec.with_alphabet_table(memory.get(address..)?)
+    }
+
+    /// Copy of this codec using the alphabets of the 78 byte
+    /// alphabet `table` (26 zscii codes for each of A0, A1 and
+    /// A2). Codes are translated to chars through the codec's
+    /// zscii table. A2 positions 6 and 7 are fixed to the
+    /// escape and newline and thus ignored. Fails if `table`
+    /// is too short or contains codes without a char.
+    pub fn with_alphabet_table(&self, table: &[u8]) -> Option<ZCodec> {
+        if table.len() < 78 {
+            return None;
+        }
+        let lookup = |code: &u8| self.zscii.to_char(*code as u16);
+        let a0: Option<Vec<char>> = table[0..26].iter().map(lookup).collect();
+        let a1: Option<Vec<char>> = table[26..52].iter().map(lookup).collect();
+        let mut a2: Vec<char> = vec!['\n'];
+        a2.append(&mut table[54..78].iter().map(lookup).collect::<Option<Vec<char>>>()?);
+        Some(ZCodec {
+            a0: a0?,
+            a1: a1?,
+            a2,
+            ..self.clone()
+        })
+    }
+
+    /// Number of abbreviations that can be referred to.
+    pub fn abbreviation_count(&self) -> usize {
+        match self.version {
+            ZVersion::V1 => 0,
+            ZVersion::V2 => 32,
+            _ => 96,
+        }
+    }
+
+    /// Map a single char to z-chars. Versions 1 and 2 use the
+    /// (temporary) shift characters 2 and 3 instead of 4 and 5,
+    /// the encoder never locks the shift.
+    pub fn zmap(&self, c: char) -> Option<Vec<u8>> {
+        let (zchars, n) = self.map_char(c)?;
+        Some(zchars[..n].to_vec())
+    }
+
+    /// Same as `zmap` without allocating, mapping through the
+    /// free `zmap`'s alphabet and zscii lookup.
+    fn map_char(&self, c: char) -> Option<([u8; 4], usize)> {
+        if self.version == ZVersion::V1 && c == '\n' {
+            return Some(([1, 0, 0, 0], 1));
+        }
+        let (mut zchars, n) = zmap_zchars(c, &self.a0, &self.a1, &self.a2, &self.zscii)?;
+        // shift 4 and 5 become 2 and 3
+        if n > 1 && self.version <= ZVersion::V2 {
+            zchars[0] -= 2;
+        }
+        Some((zchars, n))
+    }
+
+    /// Encode `s` into a packed z-string. Chars that cannot
+    /// be represented are dropped.
+    pub fn encode(&self, s: String) -> Vec<u8> {
+        let zchars = ZCharEncoder::new(self, s.chars()).filter_map(Result::ok);
+        ZPacker::new(zchars).collect()
+    }
+
+    /// Same as `encode` but fails on the first char that
+    /// cannot be represented.
+    pub fn try_encode(&self, s: String) -> Result<Vec<u8>, ZTextError> {
+        let zchars = ZCharEncoder::new(self, s.chars()).collect::<Result<Vec<u8>, ZTextError>>()?;
+        Ok(zpack(zchars))
+    }
+
+    /// Encode `word` the way dictionary entries (and words
+    /// looked up in the dictionary) are encoded: the word is
+    /// folded to lower case, cut off after 6 z-chars (9 since
+    /// version 4), even in the middle of a zscii escape, and
+    /// padded with 5s. The result thus always occupies 4 (6)
+    /// bytes with the end bit set on the last word. Z-chars
+    /// come from the mapping `zmap` uses and are packed by
+    /// `zpack`.
+    pub fn encode_dictionary_word(&self, word: &str) -> Vec<u8> {
+        let length = if self.version <= ZVersion::V3 { 6 } else { 9 };
+        let chars = word.chars().flat_map(char::to_lowercase);
+        let mut v: Vec<u8> = ZCharEncoder::new(self, chars)
+            .filter_map(Result::ok)
+            .take(length)
+            .collect();
+        v.resize(length, 0x05);
+        zpack(v)
+    }
+
+    /// Same as `encode` but substitutes abbreviation
+    /// references wherever this shortens the result. Only the
+    /// first `abbreviation_count` entries of `abbreviations` are
+    /// considered since there is no way to refer to any further
+    /// entry. The choice between literal chars and abbreviations
+    /// is made by dynamic programming over the input, so the
+    /// packed result is as short as possible for the given
+    /// abbreviations.
+    pub fn encode_abbrev(&self, s: String, abbreviations: &[String]) -> Vec<u8> {
+        let chars: Vec<char> = s.chars().collect();
+        let abbrevs: Vec<Vec<char>> = abbreviations
+            .iter()
+            .take(self.abbreviation_count())
+            .map(|a| a.chars().collect())
+            .collect();
+
+        // literal z-chars of every input char (empty if unrepresentable)
+        let literals: Vec<Vec<u8>> = chars
+            .iter()
+            .map(|&c| self.zmap(c).unwrap_or_default())
+            .collect();
+
+        // cost[i] is the least number of z-chars needed to encode
+        // chars[i..], choice[i] the abbreviation used at i (if any)
+        let n = chars.len();
+        let mut cost: Vec<usize> = vec![0; n + 1];
+        let mut choice: Vec<Option<usize>> = vec![None; n + 1];
+        for i in (0..n).rev() {
+            cost[i] = literals[i].len() + cost[i + 1];
+            for (index, a) in abbrevs.iter().enumerate() {
+                if a.is_empty() || !chars[i..].starts_with(a) {
+                    continue;
+                }
+                let c = 2 + cost[i + a.len()];
+                if c < cost[i] {
+                    cost[i] = c;
+                    choice[i] = Some(index);
+                }
+            }
+        }
+
+        let mut v: Vec<u8> = Vec::new();
+        let mut i = 0;
+        while i < n {
+            match choice[i] {
+                Some(index) => {
+                    v.push(1 + (index / 32) as u8);
+                    v.push((index % 32) as u8);
+                    i += abbrevs[index].len();
+                },
+                None => {
+                    v.extend_from_slice(&literals[i]);
+                    i += 1;
+                }
+            }
+        }
+        zpack(v)
+    }
+
+    /// Decode the packed z-string `z`. Abbreviation references
+    /// are expanded using `abbreviations`; abbreviation strings
+    /// must not contain abbreviations themselves, such nested
+    /// references are dropped just like undecodable z-chars.
+    /// All of `z` is decoded, words following the one having
+    /// the end bit included; use `decode_at` to stop there.
+    pub fn decode(&self, z: Vec<u8>, abbreviations: Option<&Abbreviations>) -> String {
+        ZCharDecoder::new(self, zunpack(z).into_iter(), abbreviations)
+            .filter_map(Result::ok)
+            .collect()
+    }
+
+    /// Same as `decode` but fails on the first problem found
+    /// instead of dropping z-chars: undefined zscii codes,
+    /// escapes or abbreviation references cut short by the end
+    /// of the string, references to missing (or nested)
+    /// abbreviations and a last word without the end bit.
+    pub fn try_decode(
+        &self,
+        z: Vec<u8>,
+        abbreviations: Option<&Abbreviations>
+    ) -> Result<String, ZTextError> {
+        if !z.is_empty() && (!z.len().is_multiple_of(2) || z[z.len() - 2] & 0x80 == 0) {
+            return Err(ZTextError::MissingEndBit);
+        }
+        ZCharDecoder::new(self, zunpack(z).into_iter(), abbreviations).collect()
+    }
+
+    /// Decode the z-string starting at byte `address` of the
+    /// story's `memory`, reading words until the one having the
+    /// end bit. Returns the text together with the number of
+    /// bytes the z-string occupies, or `None` if the memory ends
+    /// before the end bit. Undecodable z-chars are dropped.
+    pub fn decode_at(
+        &self,
+        memory: &[u8],
+        address: usize,
+        abbreviations: Option<&Abbreviations>
+    ) -> Option<(String, usize)> {
+        let mut decoder = ZCharDecoder::new(self, ZChars::new(memory.get(address..)?), abbreviations);
+        let s: String = decoder.by_ref().filter_map(Result::ok).collect();
+        if !decoder.zchars().terminated() {
+            return None;
+        }
+        Some((s, decoder.zchars().consumed()))
+    }
+
+    /// Fallible variant of `decode_at`, see `try_decode`.
+    pub fn try_decode_at(
+        &self,
+        memory: &[u8],
+        address: usize,
+        abbreviations: Option<&Abbreviations>
+    ) -> Result<(String, usize), ZTextError> {
+        let bytes = memory.get(address..).ok_or(ZTextError::MissingEndBit)?;
+        let mut decoder = ZCharDecoder::new(self, ZChars::new(bytes), abbreviations);
+        let s = decoder.by_ref().collect::<Result<String, ZTextError>>()?;
+        if !decoder.zchars().terminated() {
+            return Err(ZTextError::MissingEndBit);
+        }
+        Ok((s, decoder.zchars().consumed()))
+    }
+
+    /// Decode a single char (or abbreviation reference) from
+    /// `zchars`, updating the shift `state`. Returns `None` once
+    /// `zchars` is exhausted.
+    fn step<I: Iterator<Item = u8>>(
+        &self,
+        state: &mut DecodeState,
+        zchars: &mut I
+    ) -> Option<Step> {
+        let z = zchars.next()?;
+        let position = state.position;
+        state.position += 1;
+        let alphabet = state.shift.take().unwrap_or(state.lock);
+        let early = self.version <= ZVersion::V2;
+
+        let step = match z {
+            0 => Step::Char(' '),
+            1 if self.version == ZVersion::V1 => Step::Char('\n'),
+            1..=3 if !early || z == 1 => match zchars.next() {
+                Some(x) => {
+                    state.position += 1;
+                    Step::Abbreviation(32 * (z - 1) + (x & 0x1f), position)
+                },
+                None => Step::Error(ZTextError::TruncatedAbbreviation { position }),
+            },
+            2 | 3 => { state.shift = Some((state.lock + z - 1) % 3); Step::Skip },
+            4 | 5 if early => { state.lock = (state.lock + z - 3) % 3; Step::Skip },
+            4 | 5 => { state.shift = Some(z - 3); Step::Skip },
+            6 if alphabet == 2 => match (zchars.next(), zchars.next()) {
+                (Some(hb), Some(lb)) => {
+                    state.position += 2;
+                    let code = ((hb as u16 & 0x1f) << 5) | (lb as u16 & 0x1f);
+                    match self.zscii.to_char(code) {
+                        Some(c) => Step::Char(c),
+                        None => Step::Error(ZTextError::InvalidZscii { code, position }),
+                    }
+                },
+                _ => Step::Error(ZTextError::TruncatedEscape { position }),
+            },
+            6..=31 => {
+                let c = match alphabet {
+                    0 => self.a0.get(z as usize - 6),
+                    1 => self.a1.get(z as usize - 6),
+                    _ => self.a2.get(z as usize - 7),
+                };
+                match c {
+                    Some(c) => Step::Char(*c),
+                    None => Step::Skip,
+                }
+            },
+            _ => Step::Skip,
+        };
+        Some(step)
+    }
+}
+
+/// Shift state of the decoder: the locked alphabet (versions
+/// 1 and 2 only), the alphabet of the next char if shifted
+/// temporarily and the number of z-chars read so far.
+#[derive(Clone, Copy, Debug, Default)]
+struct DecodeState {
+    lock: u8,
+    shift: Option<u8>,
+    position: usize,
+}
+
+/// Outcome of decoding the next z-char(s).
+enum Step {
+    Char(char),
+    Abbreviation(u8, usize),
+    Error(ZTextError),
+    Skip,
+}
+
+/// Iterator over the z-chars of a packed z-string. It stops
+/// after the word having the end bit or at the end of the
+/// underlying bytes, whatever comes first. Unlike `zunpack`
+/// it reads the bytes in place, so it can walk strings
+/// embedded in story memory.
+#[derive(Clone, Debug)]
+pub struct ZChars<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    word: [u8; 3],
+    index: usize,
+    terminated: bool,
+}
+
+impl<'a> ZChars<'a> {
+    pub fn new(bytes: &'a [u8]) -> ZChars<'a> {
+        ZChars { bytes, offset: 0, word: [0; 3], index: 3, terminated: false }
+    }
+
+    /// Number of bytes read so far.
+    pub fn consumed(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether the word having the end bit has been read.
+    pub fn terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl Iterator for ZChars<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.index == 3 {
+            if self.terminated || self.offset + 2 > self.bytes.len() {
+                return None;
+            }
+            let hb = self.bytes[self.offset];
+            let lb = self.bytes[self.offset + 1];
+            self.word = [(hb & 0x7c) >> 2, ((hb & 0x03) << 3) | ((lb & 0xe0) >> 5), lb & 0x1f];
+            self.terminated = hb & 0x80 != 0;
+            self.offset += 2;
+            self.index = 0;
+        }
+        self.index += 1;
+        Some(self.word[self.index - 1])
+    }
+}
+
+/// Iterator decoding z-chars into chars, keeping track of the
+/// shift state across z-chars. Abbreviations are expanded on
+/// the fly, errors are reported in place of the char that
+/// could not be decoded. Use `filter_map(Result::ok)` for
+/// lossy decoding.
+pub struct ZCharDecoder<'a, I: Iterator<Item = u8>> {
+    codec: &'a ZCodec,
+    zchars: I,
+    abbreviations: Option<&'a Abbreviations>,
+    state: DecodeState,
+    /// the abbreviation string being expanded, with the
+    /// position of the reference to it
+    expansion: Option<(ZChars<'a>, DecodeState, usize)>,
+}
+
+impl<'a, I: Iterator<Item = u8>> ZCharDecoder<'a, I> {
+    pub fn new(
+        codec: &'a ZCodec,
+        zchars: I,
+        abbreviations: Option<&'a Abbreviations>
+    ) -> ZCharDecoder<'a, I> {
+        ZCharDecoder { codec, zchars, abbreviations, state: DecodeState::default(), expansion: None }
+    }
+
+    /// The underlying z-char iterator, e.g. to find out how
+    /// many bytes a `ZChars` iterator has consumed.
+    pub fn zchars(&self) -> &I {
+        &self.zchars
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for ZCharDecoder<'_, I> {
+    type Item = Result<char, ZTextError>;
+
+    fn next(&mut self) -> Option<Result<char, ZTextError>> {
+        loop {
+            // abbreviation strings must not refer to abbreviations
+            if let Some((zchars, state, position)) = self.expansion.as_mut() {
+                match self.codec.step(state, zchars) {
+                    Some(Step::Char(c)) => return Some(Ok(c)),
+                    Some(Step::Error(e)) => return Some(Err(e)),
+                    Some(Step::Abbreviation(..)) => {
+                        return Some(Err(ZTextError::NestedAbbreviation { position: *position }));
+                    },
+                    Some(Step::Skip) => continue,
+                    None => { self.expansion = None; continue; },
+                }
+            }
+
+            match self.codec.step(&mut self.state, &mut self.zchars)? {
+                Step::Char(c) => return Some(Ok(c)),
+                Step::Error(e) => return Some(Err(e)),
+                Step::Abbreviation(index, position) => {
+                    let expansion = Some(index)
+                        .filter(|&index| (index as usize) < self.codec.abbreviation_count())
+                        .and_then(|index| self.abbreviations?.get(index as usize));
+                    match expansion {
+                        Some(z) => {
+                            self.expansion = Some((ZChars::new(z), DecodeState::default(), position));
+                        },
+                        None => {
+                            return Some(Err(ZTextError::AbbreviationOutOfRange { index, position }));
+                        },
+                    }
+                },
+                Step::Skip => continue,
+            }
+        }
+    }
+}
+
+/// Iterator encoding chars into z-chars, the reverse of
+/// `ZCharDecoder`. Chars that cannot be represented are
+/// reported as errors.
+pub struct ZCharEncoder<'a, I: Iterator<Item = char>> {
+    codec: &'a ZCodec,
+    chars: I,
+    position: usize,
+    pending: [u8; 4],
+    index: usize,
+    count: usize,
+}
+
+impl<'a, I: Iterator<Item = char>> ZCharEncoder<'a, I> {
+    pub fn new(codec: &'a ZCodec, chars: I) -> ZCharEncoder<'a, I> {
+        ZCharEncoder { codec, chars, position: 0, pending: [0; 4], index: 0, count: 0 }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for ZCharEncoder<'_, I> {
+    type Item = Result<u8, ZTextError>;
+
+    fn next(&mut self) -> Option<Result<u8, ZTextError>> {
+        if self.index == self.count {
+            let c = self.chars.next()?;
+            let position = self.position;
+            self.position += 1;
+            match self.codec.map_char(c) {
+                Some((zchars, count)) => {
+                    self.pending = zchars;
+                    self.count = count;
+                    self.index = 0;
+                },
+                None => return Some(Err(ZTextError::Unrepresentable { c, position })),
+            }
+        }
+        self.index += 1;
+        Some(Ok(self.pending[self.index - 1]))
+    }
+}
+
+/// Iterator packing z-chars into the bytes of a z-string,
+/// the lazy counterpart of `zpack`: three z-chars form a
+/// word, the last word is padded with 5s and has the end
+/// bit set.
+pub struct ZPacker<I: Iterator<Item = u8>> {
+    zchars: Peekable<I>,
+    low: Option<u8>,
+}
+
+impl<I: Iterator<Item = u8>> ZPacker<I> {
+    pub fn new(zchars: I) -> ZPacker<I> {
+        ZPacker { zchars: zchars.peekable(), low: None }
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for ZPacker<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if let Some(lb) = self.low.take() {
+            return Some(lb);
+        }
+        let chunk0 = self.zchars.next()? & 0x1f;
+        let chunk1 = self.zchars.next().unwrap_or(5) & 0x1f;
+        let chunk2 = self.zchars.next().unwrap_or(5) & 0x1f;
+        let mut hb = (chunk0 << 2) | (chunk1 >> 3);
+        if self.zchars.peek().is_none() {
+            hb |= 0x80;
+        }
+        self.low = Some((chunk1 << 5) | chunk2);
+        Some(hb)
+    }
+}
+
+/// Problems the text codec may run into. Positions refer to
+/// the char index of the input when encoding and to the index
+/// of the offending z-char when decoding.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ZTextError {
+    /// char cannot be represented by the alphabets
+    /// nor by the zscii table
+    Unrepresentable { c: char, position: usize },
+
+    /// zscii escape sequence ends prematurely
+    TruncatedEscape { position: usize },
+
+    /// zscii escape refers to an undefined zscii code
+    InvalidZscii { code: u16, position: usize },
+
+    /// abbreviation reference lacks its second z-char
+    TruncatedAbbreviation { position: usize },
+
+    /// abbreviation reference to an entry that does not
+    /// exist (or is not available in this version)
+    AbbreviationOutOfRange { index: u8, position: usize },
+
+    /// abbreviation reference inside an abbreviation string,
+    /// `position` is the one of the outer reference
+    NestedAbbreviation { position: usize },
+
+    /// last word of the string does not have the end bit set
+    MissingEndBit,
+}
+
+impl fmt::Display for ZTextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZTextError::Unrepresentable { c, position } => {
+                write!(f, "char {:?} at position {} cannot be represented", c, position)
+            },
+            ZTextError::TruncatedEscape { position } => {
+                write!(f, "truncated zscii escape at z-char {}", position)
+            },
+            ZTextError::InvalidZscii { code, position } => {
+                write!(f, "undefined zscii code {} at z-char {}", code, position)
+            },
+            ZTextError::TruncatedAbbreviation { position } => {
+                write!(f, "truncated abbreviation reference at z-char {}", position)
+            },
+            ZTextError::AbbreviationOutOfRange { index, position } => {
+                write!(f, "abbreviation {} at z-char {} does not exist", index, position)
+            },
+            ZTextError::NestedAbbreviation { position } => {
+                write!(f, "nested abbreviation at z-char {}", position)
+            },
+            ZTextError::MissingEndBit => {
+                write!(f, "z-string is not terminated by the end bit")
+            },
+        }
+    }
+}
+
+impl core::error::Error for ZTextError {}
+
 pub fn string_to_zstring(s: String) -> Vec<u8> {
-    let mut v: Vec<u8> = Vec::new();
-    for x in s.chars() {
-        let chars = zmap(
-            x, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
-        );
-        match chars {
-            Some(c) => {v.append(&mut c.clone())},
-            None => {}
+    ZCodec::new(ZVersion::V3).encode(s)
+}
+
+/// Fallible variant of `string_to_zstring`, see `ZCodec::try_encode`.
+pub fn try_string_to_zstring(s: String) -> Result<Vec<u8>, ZTextError> {
+    ZCodec::new(ZVersion::V3).try_encode(s)
+}
+
+/// Same as `string_to_zstring` but substitutes abbreviation
+/// references wherever this shortens the result, see
+/// `ZCodec::encode_abbrev`.
+pub fn string_to_zstring_abbrev(s: String, abbreviations: &[String]) -> Vec<u8> {
+    ZCodec::new(ZVersion::V3).encode_abbrev(s, abbreviations)
+}
+
+/// Encode `word` as a dictionary entry of a story file
+/// of the given `version`, see `ZCodec::encode_dictionary_word`.
+pub fn encode_dictionary_word(word: &str, version: ZVersion) -> Vec<u8> {
+    ZCodec::new(version).encode_dictionary_word(word)
+}
+
+/// Pick up to `count` abbreviations (at most 96) for the
+/// strings of a game's `corpus` so that the total packed size
+/// of the corpus, as produced by `string_to_zstring_abbrev`,
+/// becomes as small as possible. This is the problem Inform
+/// solves with its `-u` switch.
+///
+/// Abbreviations are chosen greedily: candidates are the
+/// substrings occurring more than once, ranked by their
+/// estimated saving, and in every round the best few of them
+/// are measured against the actual encoding. The abbreviation
+/// string has to be stored in the story file as well, so only
+/// candidates saving more than their own packed size are taken.
+///
+/// The result lists the abbreviations in the order they were
+/// chosen together with the number of bytes each one saves on
+/// top of the previous ones, compared to the output of
+/// `string_to_zstring`.
+pub fn optimize_abbreviations(corpus: &[String], count: usize) -> Vec<(String, usize)> {
+    // longest abbreviation considered and number of
+    // candidates measured exactly in each round
+    const MAX_LEN: usize = 20;
+    const POOL: usize = 16;
+
+    // count the substrings one length at a time; a substring
+    // can only repeat if the one a char shorter does, so only
+    // the positions of repeated substrings are extended and
+    // memory stays bounded by the repeated ones
+    let texts: Vec<Vec<char>> = corpus.iter().map(|s| s.chars().collect()).collect();
+    let mut occurrences: BTreeMap<String, usize> = BTreeMap::new();
+    let mut positions: Vec<(usize, usize)> = texts.iter()
+        .enumerate()
+        .flat_map(|(t, chars)| (0..chars.len()).map(move |i| (t, i)))
+        .collect();
+    for len in 2..=MAX_LEN {
+        let mut counts: BTreeMap<&[char], usize> = BTreeMap::new();
+        positions.retain(|&(t, i)| i + len <= texts[t].len());
+        for &(t, i) in positions.iter() {
+            *counts.entry(&texts[t][i..i + len]).or_insert(0) += 1;
+        }
+        positions.retain(|&(t, i)| counts[&texts[t][i..i + len]] > 1);
+        if positions.is_empty() {
+            break;
         }
+        occurrences.extend(counts.into_iter()
+            .filter(|(_, n)| *n > 1)
+            .map(|(sub, n)| (sub.iter().collect(), n)));
     }
-    let ret = zpack(v);
-    return ret;
+
+    // estimated saving: every occurrence replaces the literal
+    // z-chars by two z-chars, minus the cost of storing it
+    let codec = ZCodec::new(ZVersion::V3);
+    let mut candidates: Vec<(String, isize)> = occurrences
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(sub, n)| {
+            let zchars: usize = sub.chars()
+                .map(|c| codec.zmap(c).map_or(0, |z| z.len()))
+                .sum();
+            let stored = string_to_zstring(sub.clone()).len();
+            let estimate = n as isize * (zchars as isize - 2) * 2 / 3 - stored as isize;
+            (sub, estimate)
+        })
+        .filter(|(_, estimate)| *estimate > 0)
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut sizes: Vec<usize> = corpus
+        .iter()
+        .map(|s| string_to_zstring(s.clone()).len())
+        .collect();
+    let mut chosen: Vec<String> = Vec::new();
+    let mut result: Vec<(String, usize)> = Vec::new();
+
+    while chosen.len() < count.min(96) && !candidates.is_empty() {
+        // (candidate index, net saving, corpus saving) of the
+        // best candidate and the string sizes it results in
+        let mut best: Option<(usize, isize, usize)> = None;
+        let mut best_sizes: Vec<(usize, usize)> = Vec::new();
+        let mut useless: Vec<usize> = Vec::new();
+
+        for (k, (candidate, _)) in candidates.iter().enumerate().take(POOL) {
+            let mut trial = chosen.clone();
+            trial.push(candidate.clone());
+            let mut saved = 0;
+            let mut updated: Vec<(usize, usize)> = Vec::new();
+            for (n, s) in corpus.iter().enumerate() {
+                if !s.contains(candidate.as_str()) {
+                    continue;
+                }
+                let size = string_to_zstring_abbrev(s.clone(), &trial).len();
+                if size < sizes[n] {
+                    saved += sizes[n] - size;
+                    updated.push((n, size));
+                }
+            }
+            let net = saved as isize - string_to_zstring(candidate.clone()).len() as isize;
+            if net <= 0 {
+                useless.push(k);
+            } else if best.is_none_or(|b| net > b.1) {
+                best = Some((k, net, saved));
+                best_sizes = updated;
+            }
+        }
+
+        if let Some((k, _, saved)) = best {
+            for (n, size) in best_sizes {
+                sizes[n] = size;
+            }
+            chosen.push(candidates[k].0.clone());
+            result.push((candidates[k].0.clone(), saved));
+            useless.push(k);
+        }
+        useless.sort_unstable();
+        for k in useless.into_iter().rev() {
+            candidates.remove(k);
+        }
+    }
+    result
 }
 
 pub fn zstring_to_string(z: Vec<u8>) -> String {
-    let v = zunpack(z);
-    let mut s = String::new();
-    let mut i = 0;
-    while i < v.len() {
-        let zchars = match v.len() - i {
-            0 => {break;},
-            1 => {vec![v[i]]},
-            2 => {vec![v[i], v[i+1]]},
-            3 => {vec![v[i], v[i+1], v[i+2]]},
-            _ => {vec![v[i], v[i+1], v[i+2], v[i+3]]},
-        };
-        let c = zunmap(
-            zchars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
-        );
-        match c {
-            Some(x) => {s.push(x.0); i += x.1 as usize;},
-            None => { i+= 1; }
+    ZCodec::new(ZVersion::V3).decode(z, None)
+}
+
+/// Fallible variant of `zstring_to_string`, see `ZCodec::try_decode`.
+pub fn try_zstring_to_string(z: Vec<u8>) -> Result<String, ZTextError> {
+    ZCodec::new(ZVersion::V3).try_decode(z, None)
+}
+
+/// Same as `zstring_to_string` but expands abbreviation
+/// references (z-char 1, 2 or 3 followed by another z-char)
+/// into the strings found in `abbreviations`. Abbreviation
+/// strings must not contain abbreviations themselves, such
+/// nested references are dropped.
+pub fn zstring_to_string_abbrev(z: Vec<u8>, abbreviations: &Abbreviations) -> String {
+    ZCodec::new(ZVersion::V3).decode(z, Some(abbreviations))
+}
+
+/// Determine the index into the abbreviations table
+/// referenced by the leading z-chars of `zchars`. Z-char
+/// 1, 2 or 3 followed by z-char `x` refers to entry
+/// `32 * (z - 1) + x`.
+pub fn abbreviation_index(zchars: Vec<u8>) -> Option<u8> {
+    if zchars.len() < 2 {
+        return None;
+    }
+    match zchars[0] {
+        1..=3 => Some(32 * (zchars[0] - 1) + (zchars[1] & 0x1f)),
+        _ => None,
+    }
+}
+
+/// The abbreviation strings of a story file. Each entry
+/// holds the packed z-string (including the end bit) an
+/// abbreviation reference expands to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Abbreviations {
+    strings: Vec<Vec<u8>>,
+}
+
+impl Abbreviations {
+    /// Read the abbreviations table located at byte address
+    /// `table` inside the story's `memory`. The table consists
+    /// of `count` word addresses (96 since version 3, 32 in
+    /// version 2) pointing to the abbreviation strings.
+    pub fn from_table(memory: &[u8], table: usize, count: usize) -> Option<Abbreviations> {
+        let mut addresses: Vec<u16> = Vec::new();
+        for n in 0..count {
+            let hb = *memory.get(table + 2 * n)? as u16;
+            let lb = *memory.get(table + 2 * n + 1)? as u16;
+            addresses.push((hb << 8) | lb);
         }
+        Abbreviations::from_addresses(memory, addresses)
+    }
+
+    /// Collect the abbreviation strings stored at the given
+    /// word addresses (i.e. half the byte address) of `memory`.
+    pub fn from_addresses(memory: &[u8], addresses: Vec<u16>) -> Option<Abbreviations> {
+        let mut strings: Vec<Vec<u8>> = Vec::new();
+        for address in addresses {
+            strings.push(read_zstring(memory, 2 * address as usize)?);
+        }
+        Some(Abbreviations { strings })
+    }
+
+    /// Use already packed z-strings as abbreviations.
+    pub fn from_zstrings(strings: Vec<Vec<u8>>) -> Abbreviations {
+        Abbreviations { strings }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Vec<u8>> {
+        self.strings.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
     }
-    return s;
 }
 
-mod tests {
-    use std::string;
+/// Read the big endian word at byte `address` of `memory`.
+pub fn read_word(memory: &[u8], address: usize) -> Option<u16> {
+    let hb = *memory.get(address)? as u16;
+    let lb = *memory.get(address.checked_add(1)?)? as u16;
+    Some((hb << 8) | lb)
+}
+
+/// Write `value` as big endian word to byte `address` of
+/// `memory`. Returns `None` if the word does not fit.
+pub fn write_word(memory: &mut [u8], address: usize, value: u16) -> Option<()> {
+    let bytes = memory.get_mut(address..address.checked_add(2)?)?;
+    bytes.copy_from_slice(&value.to_be_bytes());
+    Some(())
+}
 
-    use super::{zpack, zunpack};
-    use crate::zscii::{A0, A1, A2, S, ZSCIITAB};
+/// Copy the z-string starting at byte address `address`
+/// out of `memory`. The string ends with the first word
+/// having its top bit set.
+fn read_zstring(memory: &[u8], address: usize) -> Option<Vec<u8>> {
+    let mut v: Vec<u8> = Vec::new();
+    let mut p = address;
+    loop {
+        let hb = *memory.get(p)?;
+        let lb = *memory.get(p + 1)?;
+        v.push(hb);
+        v.push(lb);
+        if hb & 0x80 != 0 {
+            return Some(v);
+        }
+        p += 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        zpack, zunpack, Abbreviations, ZCharDecoder, ZCharEncoder, ZChars, ZCodec, ZPacker,
+        ZTextError,
+    };
+    use crate::zversion::ZVersion;
+    use crate::zscii::{A0, A1, A2, S, ZSCIITAB, ZsciiTable};
 
     #[test]
     fn test_zpack() {
@@ -353,7 +1165,7 @@ mod tests {
         for c in "abcdefghijklmnopqrstuvwxyz".chars() {
             let zchar = super::zmap(
                 c,
-                A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(zchar, Some(vec![c as u8 - 0x61 + 6]));
         }
@@ -364,24 +1176,25 @@ mod tests {
         for c in "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars() {
             let zchar = super::zmap(
                 c,
-                A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(zchar, Some(vec![0x04, c as u8 - 0x41 + 6]));
         }
     }
 
     #[test]
+    #[allow(clippy::explicit_counter_loop)]
     fn test_zmap_maps_a2_correctly() {
         for c in "^".chars() {
             let zchar = super::zmap(
-                c, A0.to_vec(),  A1.to_vec(),  A2.to_vec(), ZSCIITAB.to_vec()
+                c, A0.to_vec(),  A1.to_vec(),  A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(zchar, Some(vec![0x05, 7u8]));
         }
         
         for c in "0123456789".chars() {
             let zchar = super::zmap(
-                c, A0.to_vec(),A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                c, A0.to_vec(),A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(zchar, Some(vec![0x05, c as u8 - 0x2f  + 7]));
         }
@@ -389,7 +1202,7 @@ mod tests {
         let mut index = 18u8;
         for c in ".,!?_#\'\"/\\-:()".chars() {
             let zchar = super::zmap(
-                c, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                c, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(zchar, Some(vec![0x05, index]));
             index += 1;
@@ -400,7 +1213,7 @@ mod tests {
     fn test_zmap_maps_zscii_correctly() {
         for c in S.iter() {
             let zchar = super::zmap(
-                *c, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                *c, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             let index = ZSCIITAB.iter().position(|&x| x == Some(*c)).unwrap();
             assert_eq!(zchar, Some(vec![0x05, 0x06, (index as u8 & 0xe0) >> 5, index as u8 & 0x1f]));
@@ -410,7 +1223,7 @@ mod tests {
     #[test]
     fn test_zmap_maps_space_correctly() {
         let zchar = super::zmap(
-            ' ', A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+            ' ', A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
         );
         assert_eq!(zchar, Some(vec![0x00]));
     }
@@ -420,7 +1233,7 @@ mod tests {
         for seq in 6..31 {
             let chars: Vec<u8> = vec![seq];
             let c = super::zunmap(
-                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(c, Some(((seq - 6 + 0x61) as char, 1u8)));
         }
@@ -431,7 +1244,7 @@ mod tests {
         for seq in 6..31 {
             let chars: Vec<u8> = vec![0x04, seq];
             let c = super::zunmap(
-                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(c, Some(((seq - 6 + 0x41) as char, 2u8)));
         }
@@ -442,13 +1255,14 @@ mod tests {
         for seq in 7..31 {
             let chars: Vec<u8> = vec![0x05, seq];
             let c = super::zunmap(
-                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
             );
             assert_eq!(c, Some((A2[seq as usize - 7], 2u8)));
         }
     }
 
     #[test]
+    #[allow(clippy::single_match)]
     fn test_zunmap_maps_zscii_correctly() {
         for c in ZSCIITAB.iter() {
             match c {
@@ -456,7 +1270,7 @@ mod tests {
                     let index = ZSCIITAB.iter().position(|&y| y == Some(*x)).unwrap();
                     let chars: Vec<u8> = vec![0x05, 0x06, (index as u8 & 0xe0) >> 5, index as u8 & 0x1f];
                     let c = super::zunmap(
-                        chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+                        chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
                     );
                     assert_eq!(c, Some((*x,4)));
                 },
@@ -469,7 +1283,7 @@ mod tests {
     fn test_zunmap_maps_space_correctly() {
         let chars: Vec<u8> = vec![0x00];
         let c = super::zunmap(
-            chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), ZSCIITAB.to_vec()
+            chars, A0.to_vec(), A1.to_vec(), A2.to_vec(), &ZsciiTable::default()
         );
         assert_eq!(c, Some((' ', 1)));
     }
@@ -493,4 +1307,398 @@ mod tests {
         assert_eq!(string, s3);
     }
 
+    #[test]
+    fn test_zstring_to_string_abbrev_expands_abbreviations() {
+        // "the" at byte address 0x04 (word address 0x02) and
+        // "ball" at byte address 0x08 (word address 0x04)
+        let mut memory: Vec<u8> = vec![0x00, 0x02, 0x00, 0x04];
+        memory.append(&mut super::string_to_zstring("the".to_string()));
+        memory.append(&mut vec![0x00, 0x00]);
+        memory.append(&mut super::string_to_zstring("ball".to_string()));
+
+        let abbrevs = Abbreviations::from_table(&memory, 0, 2).unwrap();
+        assert_eq!(abbrevs.len(), 2);
+
+        // [1, 0] -> "the", [0] -> ' ', [1, 1] -> "ball"
+        let z = zpack(vec![0x01, 0x00, 0x00, 0x01, 0x01]);
+        assert_eq!(super::zstring_to_string_abbrev(z.clone(), &abbrevs), "the ball");
+
+        // without a table abbreviation references are skipped
+        assert_eq!(super::zstring_to_string(z), " ");
+    }
+
+    #[test]
+    fn test_zstring_to_string_abbrev_forbids_nested_abbreviations() {
+        // abbreviation 0 refers to abbreviation 1 which must not be expanded
+        let abbrevs = Abbreviations::from_zstrings(vec![
+            zpack(vec![0x06, 0x01, 0x01, 0x07]),
+            super::string_to_zstring("xyz".to_string()),
+        ]);
+        let z = zpack(vec![0x01, 0x00, 0x01, 0x01]);
+        assert_eq!(super::zstring_to_string_abbrev(z, &abbrevs), "abxyz");
+
+        // references beyond the table are dropped as well
+        let z = zpack(vec![0x03, 0x1f, 0x06]);
+        assert_eq!(super::zstring_to_string_abbrev(z, &abbrevs), "a");
+    }
+
+    #[test]
+    fn test_abbreviations_reject_strings_without_end_bit() {
+        let memory: Vec<u8> = vec![0x00, 0x01, 0x11, 0x22, 0x33];
+        assert_eq!(Abbreviations::from_table(&memory, 0, 1), None);
+        assert_eq!(Abbreviations::from_table(&memory, 0, 3), None);
+    }
+
+    #[test]
+    fn test_string_to_zstring_abbrev_uses_abbreviations() {
+        let abbreviations = vec!["the ".to_string(), "ball".to_string()];
+        let z = super::string_to_zstring_abbrev("the ball".to_string(), &abbreviations);
+        assert_eq!(z, zpack(vec![0x01, 0x00, 0x01, 0x01]));
+
+        let abbrevs = Abbreviations::from_zstrings(
+            abbreviations.iter().map(|a| super::string_to_zstring(a.clone())).collect()
+        );
+        assert_eq!(super::zstring_to_string_abbrev(z, &abbrevs), "the ball");
+    }
+
+    #[test]
+    fn test_string_to_zstring_abbrev_finds_shortest_encoding() {
+        // greedily taking "ab" would leave "cde" for literals
+        let abbreviations = vec!["ab".to_string(), "bcde".to_string()];
+        let z = super::string_to_zstring_abbrev("abcde".to_string(), &abbreviations);
+        assert_eq!(z, zpack(vec![0x06, 0x01, 0x01]));
+
+        // abbreviations must not be longer than the literal chars
+        let abbreviations = vec!["a".to_string()];
+        let z = super::string_to_zstring_abbrev("aaa".to_string(), &abbreviations);
+        assert_eq!(z, super::string_to_zstring("aaa".to_string()));
+
+        // entries beyond 96 cannot be referenced
+        let mut abbreviations = vec!["x".to_string(); 96];
+        abbreviations.push("hello".to_string());
+        let z = super::string_to_zstring_abbrev("hello".to_string(), &abbreviations);
+        assert_eq!(z, super::string_to_zstring("hello".to_string()));
+    }
+
+    #[test]
+    fn test_optimize_abbreviations_reduces_corpus_size() {
+        let corpus: Vec<String> = vec![
+            "You can see the brass lantern here.".to_string(),
+            "The brass lantern is now on.".to_string(),
+            "The brass lantern is now off.".to_string(),
+            "You can see the brass lantern here, too.".to_string(),
+            "Taken.".to_string(),
+        ];
+        let plain: usize = corpus.iter()
+            .map(|s| super::string_to_zstring(s.clone()).len())
+            .sum();
+
+        let result = super::optimize_abbreviations(&corpus, 4);
+        assert!(!result.is_empty() && result.len() <= 4);
+        assert!(result[0].0.contains("brass lantern"));
+
+        // reported savings match the actual encoding
+        let abbreviations: Vec<String> = result.iter().map(|r| r.0.clone()).collect();
+        let packed: usize = corpus.iter()
+            .map(|s| super::string_to_zstring_abbrev(s.clone(), &abbreviations).len())
+            .sum();
+        let saved: usize = result.iter().map(|r| r.1).sum();
+        assert_eq!(plain - packed, saved);
+    }
+
+    #[test]
+    fn test_optimize_abbreviations_without_repetitions() {
+        assert!(super::optimize_abbreviations(&[], 96).is_empty());
+        let corpus = vec!["xyzzy".to_string()];
+        assert!(super::optimize_abbreviations(&corpus, 96).is_empty());
+        let corpus = vec!["plugh plugh plugh".to_string()];
+        assert!(super::optimize_abbreviations(&corpus, 0).is_empty());
+    }
+
+    #[test]
+    fn test_zcodec_roundtrips_in_all_versions() {
+        let s = "West of House\nYou are standing in an open field <west> of a white house!".to_string();
+        for n in 1..=8 {
+            let codec = ZCodec::new(ZVersion::from_u8(n).unwrap());
+            let z = codec.encode(s.clone());
+            assert_eq!(codec.decode(z, None), s);
+        }
+    }
+
+    #[test]
+    fn test_zcodec_uses_version_specific_zchars() {
+        // version 1: z-char 1 is a newline, '<' is part of A2,
+        // shifts use z-chars 2 and 3
+        let v1 = ZCodec::new(ZVersion::V1);
+        assert_eq!(v1.zmap('\n'), Some(vec![0x01]));
+        assert_eq!(v1.zmap('<'), Some(vec![0x03, 27]));
+        assert_eq!(v1.zmap('A'), Some(vec![0x02, 6]));
+
+        // version 2: newline is A2 position 7, z-char 1 an abbreviation
+        let v2 = ZCodec::new(ZVersion::V2);
+        assert_eq!(v2.zmap('\n'), Some(vec![0x03, 7]));
+        assert_eq!(v2.zmap('<'), Some(vec![0x03, 6, 1, 28]));
+
+        // version 3: shifts use z-chars 4 and 5
+        let v3 = ZCodec::new(ZVersion::V3);
+        assert_eq!(v3.zmap('\n'), Some(vec![0x05, 7]));
+        assert_eq!(v3.zmap('A'), Some(vec![0x04, 6]));
+    }
+
+    #[test]
+    fn test_zcodec_decodes_shift_locks_in_early_versions() {
+        // lock A1, "AB", lock A0 (A1 shifted down twice), "a"
+        let z = zpack(vec![0x04, 6, 7, 0x05, 6]);
+        assert_eq!(ZCodec::new(ZVersion::V2).decode(z.clone(), None), "ABa");
+
+        // temporary shift relative to the locked alphabet
+        let z = zpack(vec![0x04, 6, 0x03, 6, 7]);
+        assert_eq!(ZCodec::new(ZVersion::V1).decode(z, None), "AaB");
+
+        // version 3 has no shift locks
+        let z = zpack(vec![0x04, 6, 7]);
+        assert_eq!(ZCodec::new(ZVersion::V3).decode(z, None), "Ab");
+    }
+
+    #[test]
+    fn test_zcodec_limits_abbreviations_by_version() {
+        let abbrevs = Abbreviations::from_zstrings(vec![
+            super::string_to_zstring("one".to_string()); 40
+        ]);
+
+        // version 2 only knows z-char 1 (first 32 entries)
+        let z = zpack(vec![0x01, 0x02, 0x00, 0x06]);
+        assert_eq!(ZCodec::new(ZVersion::V2).decode(z, Some(&abbrevs)), "one a");
+        let z = zpack(vec![0x02, 0x06]);
+        assert_eq!(ZCodec::new(ZVersion::V2).decode(z, Some(&abbrevs)), "A");
+
+        // version 1 has no abbreviations at all
+        let abbreviations = vec!["hello".to_string()];
+        let v1 = ZCodec::new(ZVersion::V1);
+        let z = v1.encode_abbrev("hello".to_string(), &abbreviations);
+        assert_eq!(z, v1.encode("hello".to_string()));
+    }
+
+    #[test]
+    fn test_zcodec_loads_alphabet_table_from_story() {
+        // swap lower and upper case, use '*' instead of '0'
+        let mut table: Vec<u8> = Vec::new();
+        table.extend(b"ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        table.extend(b"abcdefghijklmnopqrstuvwxyz");
+        table.extend(b"  *123456789.,!?_#'\"/\\-:()");
+
+        let mut memory: Vec<u8> = vec![0; 0x40];
+        memory[0] = 5;
+        memory[0x35] = 0x40;
+        memory.append(&mut table);
+
+        let codec = ZCodec::from_story(&memory).unwrap();
+        assert_eq!(codec.zmap('A'), Some(vec![6]));
+        assert_eq!(codec.zmap('a'), Some(vec![0x04, 6]));
+        assert_eq!(codec.zmap('*'), Some(vec![0x05, 8]));
+        assert_eq!(codec.zmap('\n'), Some(vec![0x05, 7]));
+        assert_eq!(codec.decode(zpack(vec![6, 0x04, 7, 0x05, 8, 0x05, 7]), None), "Ab*\n");
+
+        // version 3 stories ignore the header word
+        memory[0] = 3;
+        assert_eq!(ZCodec::from_story(&memory).unwrap(), ZCodec::new(ZVersion::V3));
+
+        // truncated tables and undefined zscii codes are rejected
+        memory[0] = 5;
+        assert_eq!(ZCodec::from_story(&memory[..0x60]), None);
+        memory[0x40] = 1;
+        assert_eq!(ZCodec::from_story(&memory), None);
+    }
+
+    #[test]
+    fn test_zcodec_uses_unicode_table() {
+        let mut codec = ZCodec::new(ZVersion::V5);
+        codec.zscii = ZsciiTable::from_unicode_table(&[0x430, 0x431]).unwrap();
+        assert_eq!(codec.zmap('б'), Some(vec![0x05, 0x06, 4, 28]));
+        assert_eq!(codec.zmap('ä'), None);
+        let z = codec.encode("аб".to_string());
+        assert_eq!(codec.decode(z, None), "аб");
+    }
+
+    #[test]
+    fn test_try_string_to_zstring_reports_unrepresentable_chars() {
+        let s = "Grüß Gott!".to_string();
+        assert_eq!(super::try_string_to_zstring(s.clone()), Ok(super::string_to_zstring(s)));
+
+        let s = "ab\u{263a}c".to_string();
+        assert_eq!(
+            super::try_string_to_zstring(s.clone()),
+            Err(ZTextError::Unrepresentable { c: '\u{263a}', position: 2 })
+        );
+        // the lossy variant drops the char
+        assert_eq!(super::zstring_to_string(super::string_to_zstring(s)), "abc");
+    }
+
+    #[test]
+    fn test_try_zstring_to_string_reports_errors() {
+        // padding is fine
+        let z = zpack(vec![6, 7, 8, 9]);
+        assert_eq!(super::try_zstring_to_string(z), Ok("abcd".to_string()));
+
+        // missing end bit
+        let z = vec![0x18, 0xe8];
+        assert_eq!(super::try_zstring_to_string(z.clone()), Err(ZTextError::MissingEndBit));
+        assert_eq!(super::zstring_to_string(z), "abc");
+        assert_eq!(super::try_zstring_to_string(vec![0x98]), Err(ZTextError::MissingEndBit));
+
+        // truncated escape
+        let z = zpack(vec![6, 5, 6]);
+        assert_eq!(
+            super::try_zstring_to_string(z),
+            Err(ZTextError::TruncatedEscape { position: 2 })
+        );
+
+        // undefined zscii code 224
+        let z = zpack(vec![6, 5, 6, 7, 0]);
+        assert_eq!(
+            super::try_zstring_to_string(z.clone()),
+            Err(ZTextError::InvalidZscii { code: 224, position: 2 })
+        );
+        assert_eq!(super::zstring_to_string(z), "a");
+
+        // abbreviations
+        let abbrevs = Abbreviations::from_zstrings(vec![zpack(vec![6, 1, 0])]);
+        let codec = ZCodec::new(ZVersion::V3);
+        assert_eq!(
+            codec.try_decode(zpack(vec![6, 1, 1]), Some(&abbrevs)),
+            Err(ZTextError::AbbreviationOutOfRange { index: 1, position: 1 })
+        );
+        assert_eq!(
+            codec.try_decode(zpack(vec![6, 6, 1]), Some(&abbrevs)),
+            Err(ZTextError::TruncatedAbbreviation { position: 2 })
+        );
+        assert_eq!(
+            codec.try_decode(zpack(vec![6, 6, 1, 0]), Some(&abbrevs)),
+            Err(ZTextError::NestedAbbreviation { position: 2 })
+        );
+        assert_eq!(codec.decode(zpack(vec![1, 0]), Some(&abbrevs)), "a");
+    }
+
+    #[test]
+    fn test_encode_dictionary_word() {
+        // short words are padded with 5s
+        assert_eq!(
+            super::encode_dictionary_word("go", ZVersion::V3),
+            zpack(vec![12, 20, 5, 5, 5, 5])
+        );
+        assert_eq!(super::encode_dictionary_word("", ZVersion::V5).len(), 6);
+
+        // long words are truncated and folded to lower case
+        assert_eq!(
+            super::encode_dictionary_word("Lantern", ZVersion::V3),
+            super::encode_dictionary_word("lanter", ZVersion::V3)
+        );
+        assert_eq!(
+            super::encode_dictionary_word("MAILBOXES", ZVersion::V4),
+            super::string_to_zstring("mailboxes".to_string())
+        );
+        assert_ne!(
+            super::encode_dictionary_word("mailboxes", ZVersion::V5),
+            super::encode_dictionary_word("mailbox", ZVersion::V5)
+        );
+
+        // escapes may be cut off in the middle
+        assert_eq!(
+            super::encode_dictionary_word("abcdä", ZVersion::V3),
+            zpack(vec![6, 7, 8, 9, 5, 6])
+        );
+
+        // version 1 and 2 use their own shift characters
+        assert_eq!(
+            super::encode_dictionary_word("a.b", ZVersion::V2),
+            zpack(vec![6, 3, 18, 7, 5, 5])
+        );
+    }
+
+    #[test]
+    fn test_zcodec_decodes_strings_in_memory() {
+        let codec = ZCodec::new(ZVersion::V3);
+        let mut memory: Vec<u8> = vec![0xff, 0xff];
+        memory.append(&mut codec.encode("Hello".to_string()));
+        memory.append(&mut codec.encode("world".to_string()));
+
+        assert_eq!(codec.decode_at(&memory, 2, None), Some(("Hello".to_string(), 4)));
+        assert_eq!(codec.decode_at(&memory, 6, None), Some(("world".to_string(), 4)));
+        assert_eq!(codec.try_decode_at(&memory, 6, None), Ok(("world".to_string(), 4)));
+
+        // the end bit is missing if memory ends too early
+        assert_eq!(codec.decode_at(&memory[..8], 6, None), None);
+        assert_eq!(codec.try_decode_at(&memory[..8], 6, None), Err(ZTextError::MissingEndBit));
+        assert_eq!(codec.decode_at(&memory, 20, None), None);
+
+        // abbreviations live in the same memory
+        let abbrevs = Abbreviations::from_addresses(&memory, vec![3]).unwrap();
+        memory.append(&mut zpack(vec![0x01, 0x00, 0x00, 0x01, 0x00]));
+        assert_eq!(codec.decode_at(&memory, 10, Some(&abbrevs)), Some(("world world".to_string(), 4)));
+    }
+
+    #[test]
+    fn test_zstring_to_string_decodes_past_the_end_bit() {
+        let mut z = super::string_to_zstring("Hello".to_string());
+        z.append(&mut super::string_to_zstring("world".to_string()));
+        assert_eq!(super::zstring_to_string(z.clone()), "Helloworld");
+        assert_eq!(super::try_zstring_to_string(z.clone()), Ok("Helloworld".to_string()));
+        assert_eq!(ZCodec::new(ZVersion::V3).decode_at(&z, 0, None), Some(("Hello".to_string(), 4)));
+
+        // an odd trailing byte is padded rather than dropped
+        z.truncate(3);
+        let mut padded = z.clone();
+        padded.push(0);
+        assert_eq!(super::zstring_to_string(z.clone()), super::zstring_to_string(padded));
+        assert_eq!(super::try_zstring_to_string(z), Err(ZTextError::MissingEndBit));
+    }
+
+    #[test]
+    fn test_iterator_adapters_match_eager_functions() {
+        let codec = ZCodec::new(ZVersion::V3);
+        let s = "»Grüß Gott!« said the innkeeper.\n".to_string();
+
+        // chars -> z-chars -> bytes
+        let zchars: Vec<u8> = ZCharEncoder::new(&codec, s.chars())
+            .map(Result::unwrap)
+            .collect();
+        let packed: Vec<u8> = ZPacker::new(zchars.iter().copied()).collect();
+        assert_eq!(packed, zpack(zchars.clone()));
+        assert_eq!(packed, super::string_to_zstring(s.clone()));
+
+        // bytes -> z-chars -> chars
+        let unpacked: Vec<u8> = ZChars::new(&packed).collect();
+        assert_eq!(unpacked, zunpack(packed.clone()));
+        let decoded: String = ZCharDecoder::new(&codec, ZChars::new(&packed), None)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(decoded, s);
+
+        // both directions composed lazily
+        let roundtrip: Vec<char> = ZCharDecoder::new(
+            &codec,
+            ZCharEncoder::new(&codec, "lazy".chars()).filter_map(Result::ok),
+            None,
+        ).map(Result::unwrap).collect();
+        assert_eq!(roundtrip, vec!['l', 'a', 'z', 'y']);
+
+        assert_eq!(ZPacker::new(std::iter::empty()).count(), 0);
+    }
+
+    #[test]
+    fn test_zchars_stops_at_end_bit() {
+        let mut packed = super::string_to_zstring("abc".to_string());
+        packed.append(&mut super::string_to_zstring("def".to_string()));
+        let mut zchars = ZChars::new(&packed);
+        assert_eq!(zchars.by_ref().collect::<Vec<u8>>(), vec![6, 7, 8]);
+        assert!(zchars.terminated());
+        assert_eq!(zchars.consumed(), 2);
+
+        let codec = ZCodec::new(ZVersion::V3);
+        let mut encoder = ZCharEncoder::new(&codec, "a\u{263a}".chars());
+        assert_eq!(encoder.next(), Some(Ok(6)));
+        assert_eq!(encoder.next(), Some(Err(ZTextError::Unrepresentable { c: '\u{263a}', position: 1 })));
+        assert_eq!(encoder.next(), None);
+    }
+
 }
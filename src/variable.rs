@@ -0,0 +1,77 @@
+use crate::zcode::ZOperand;
+
+/// What a variable number refers to: 0 is the top of the
+/// routine stack, 1-15 are the locals of the current routine
+/// and 16-255 the global variables, a table of 240 words at
+/// the address given by the header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Variable {
+    Stack,
+    /// local number 0-14 (variable 1-15)
+    Local(u8),
+    /// global number 0-239 (variable 16-255)
+    Global(u8),
+}
+
+impl Variable {
+    pub fn new(number: u8) -> Variable {
+        match number {
+            0 => Variable::Stack,
+            1..=15 => Variable::Local(number - 1),
+            _ => Variable::Global(number - 16),
+        }
+    }
+
+    /// Variable number as found in operands and store bytes.
+    pub fn number(&self) -> u8 {
+        match self {
+            Variable::Stack => 0,
+            Variable::Local(n) => n + 1,
+            Variable::Global(n) => n + 16,
+        }
+    }
+
+    /// Byte address of a global given the address of the
+    /// global variables table.
+    pub fn global_address(&self, globals: u16) -> Option<usize> {
+        match self {
+            Variable::Global(n) => Some(globals as usize + 2 * *n as usize),
+            _ => None,
+        }
+    }
+}
+
+impl ZOperand {
+    /// The variable read by a variable operand. Note that
+    /// instructions taking a variable number (`inc`, `store`,
+    /// `pull`, ...) get it as the operand's value instead.
+    pub fn variable(&self) -> Option<Variable> {
+        match self {
+            ZOperand::Variable { value } => Some(Variable::new(*value)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Variable;
+    use crate::zcode::ZOperand;
+
+    #[test]
+    fn test_variable_numbers() {
+        assert_eq!(Variable::new(0), Variable::Stack);
+        assert_eq!(Variable::new(1), Variable::Local(0));
+        assert_eq!(Variable::new(15), Variable::Local(14));
+        assert_eq!(Variable::new(16), Variable::Global(0));
+        assert_eq!(Variable::new(255), Variable::Global(239));
+        for n in 0..=255 {
+            assert_eq!(Variable::new(n).number(), n);
+        }
+        assert_eq!(Variable::new(17).global_address(0x100), Some(0x102));
+        assert_eq!(Variable::new(255).global_address(0x100), Some(0x2de));
+        assert_eq!(Variable::Local(3).global_address(0x100), None);
+        assert_eq!(ZOperand::Variable { value: 2 }.variable(), Some(Variable::Local(1)));
+        assert_eq!(ZOperand::Small { value: 2 }.variable(), None);
+    }
+}
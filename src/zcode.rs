@@ -1,3 +1,9 @@
+use crate::machine::{Machine, MachineError, Status};
+use crate::opcodes::lookup;
+use crate::stream::Screen;
+use crate::util::{Abbreviations, ZCodec};
+use crate::zversion::ZVersion;
+
 #[derive(Debug)]
 pub struct ZOperands {
     // offset inside the instruction where operands start
@@ -35,9 +41,9 @@ impl ZOperands {
                 break;
             }
             match operand.unwrap() {
-                ZOperand::Large { value } => { size += 2; },
-                ZOperand::Small { value } => { size += 1; },
-                ZOperand::Variable { value } => { size += 1; },
+                ZOperand::Large { .. } => { size += 2; },
+                ZOperand::Small { .. } => { size += 1; },
+                ZOperand::Variable { .. } => { size += 1; },
             }
         }
         size
@@ -47,20 +53,95 @@ impl ZOperands {
 #[derive(Debug)]
 pub struct ZInstruction {
     opcode: u8,
-    operand_count: u8,
+    operand_count: OperandCount,
     operands: [Option<ZOperand>; 8],
+    store: Option<u8>,
+    branch: Option<ZBranch>,
+    text: Option<String>,
+    length: usize,
 }
 
 impl PartialEq for ZInstruction {
     fn eq(&self, other: &Self) -> bool {
         let mut is_eq = self.opcode == other.opcode && self.operand_count == other.operand_count;
-        for i in 0..7 {
+        for i in 0..8 {
             is_eq = is_eq && self.operands[i] == other.operands[i];
         }
         is_eq
+            && self.store == other.store
+            && self.branch == other.branch
+            && self.text == other.text
+            && self.length == other.length
+    }
+}
+
+impl ZInstruction {
+    /// Opcode number within its operand count class,
+    /// e.g. 20 for 2OP:20 (add).
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    pub fn operand_count(&self) -> OperandCount {
+        self.operand_count
+    }
+
+    pub fn operands(&self) -> &[Option<ZOperand>; 8] {
+        &self.operands
+    }
+
+    /// Variable the result is stored to (if the
+    /// instruction stores a result).
+    pub fn store(&self) -> Option<u8> {
+        self.store
+    }
+
+    pub fn branch(&self) -> Option<ZBranch> {
+        self.branch
+    }
+
+    /// Inline text of `print` and `print_ret`.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// Total number of bytes the instruction occupies.
+    pub fn length(&self) -> usize {
+        self.length
     }
 }
 
+/// Operand count class of an instruction. Opcode numbers
+/// are only unique within their class.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OperandCount {
+    Op0,
+    Op1,
+    Op2,
+    Var,
+    Ext,
+}
+
+/// Branch information following the store byte (if any)
+/// of branch instructions. The branch is taken if the
+/// condition's outcome equals `on_true`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZBranch {
+    pub on_true: bool,
+    pub target: ZBranchTarget,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZBranchTarget {
+    /// offset 0: return false from the current routine
+    ReturnFalse,
+    /// offset 1: return true from the current routine
+    ReturnTrue,
+    /// continue at address of the next instruction
+    /// plus offset minus 2
+    Offset(i16),
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum ZOperand {
     Large {
@@ -91,6 +172,17 @@ impl PartialEq for ZOperand {
     }
 }
 
+impl ZOperand {
+    /// Value of a constant operand, `None` for variables.
+    pub fn constant(&self) -> Option<u16> {
+        match self {
+            ZOperand::Large { value } => Some(u16::from_be_bytes(*value)),
+            ZOperand::Small { value } => Some(*value as u16),
+            ZOperand::Variable { .. } => None,
+        }
+    }
+}
+
 fn determine_operand_size(optypes: [u8; 2]) -> (u8, u8) {
     let mut opcount = 0;
     let mut memsize = 0;
@@ -171,229 +263,95 @@ fn determine_var_operands(optypes: [u8; 2], ops: Vec<u8>) -> [Option<ZOperand>;
     operands
 }
 
-/*pub fn decode_instruction(input: Vec<u8>) -> Option<ZInstruction> {
-    if input.len() < 1 {
-        return None;
-    }
-    let opcode = input[0];
-    match opcode {
-        // long form instruction - 2OP - small constant - small constant
-        0x00..=0x1f => {
-            if input.len() < 3 {
-                return None;
-            }
-            Some(ZInstruction {
-                opcode: opcode & 0b00011111,
-                operand_count: 2,
-                operands: [
-                    Some(ZOperand::Small{ value: input[1] }),
-                    Some(ZOperand::Small{ value: input[2] }),
-                    None, None, None, None, None, None
-                ],
-            })
-        },
+/// Decode the instruction found at address `pc` of the
+/// story's `memory`. Inline text of `print` and `print_ret`
+/// is decoded with the default alphabets of `version` and
+/// without abbreviations, see `decode_instruction_with` to
+/// use a story's own text settings. Returns `None` if the
+/// instruction is cut short by the end of `memory` or its
+/// operands are malformed.
+pub fn decode_instruction(memory: &[u8], pc: usize, version: ZVersion) -> Option<ZInstruction> {
+    decode_instruction_with(memory, pc, version, &ZCodec::new(version), None)
+}
 
-        // long form instruction - 2OP - small constant - variable
-        0x20..=0x3f => { 
-            if input.len() < 3 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00011111,
-                operand_count: 2,
-                operands: [
-                    Some(ZOperand::Small{ value: input[1] }),
-                    Some(ZOperand::Variable{ value: input[2]}),
-                    None, None, None, None, None, None
-                ],
-            
-            })
-        },
+/// Same as `decode_instruction` but decodes inline text
+/// with `codec` and `abbreviations`.
+pub fn decode_instruction_with(
+    memory: &[u8],
+    pc: usize,
+    version: ZVersion,
+    codec: &ZCodec,
+    abbreviations: Option<&Abbreviations>
+) -> Option<ZInstruction> {
+    let byte = *memory.get(pc)?;
+    let (operand_count, opcode) = match byte {
+        0xbe if version >= ZVersion::V5 => (OperandCount::Ext, *memory.get(pc + 1)?),
+        0x00..=0x7f => (OperandCount::Op2, byte & 0b11111),
+        0xb0..=0xbf => (OperandCount::Op0, byte & 0b1111),
+        0x80..=0xaf => (OperandCount::Op1, byte & 0b1111),
+        0xc0..=0xdf => (OperandCount::Op2, byte & 0b11111),
+        0xe0..=0xff => (OperandCount::Var, byte & 0b11111),
+    };
 
-        // long form instruction - 2OP - variable - small constant
-        0x40..=0x5f => { 
-            if input.len() < 3 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00011111,
-                operand_count: 2,
-                operands: [
-                    Some(ZOperand::Variable { value: input[1] }),
-                    Some(ZOperand::Small{ value: input[2]}),
-                    None, None, None, None, None, None
-                ],
-            
-            })
-        },
-        
-        // long form instruction - 2OP - variable - variable
-        0x60..=0x7f => { 
-            if input.len() < 3 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00011111,
-                operand_count: 2,
-                operands: [
-                    Some(ZOperand::Variable { value: input[1] }),
-                    Some(ZOperand::Variable { value: input[2] }),
-                    None, None, None, None, None, None
-                ],
-            
-            })
-        },
+    // opcode, (extended opcode,) 2 type bytes, 8 large operands
+    let end = memory.len().min(pc + 20);
+    let operands = decode_operands(memory[pc..end].to_vec())?;
+    let mut cursor = pc + operands.offset as usize + operands.memsize();
 
-        // short form instruction - 1OP - large constant
-        0x80..=0x8f => { 
-            if input.len() < 3 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00001111,
-                operand_count: 1,
-                operands: [
-                    Some(ZOperand::Large {
-                        value: [input[1], input[2]],
-                    }),
-                    None, None, None, None, None, None, None
-                ],
-            
-            })
-        },
-        
-        // short form instruction - 1OP - small constant
-        0x90..=0x9f => { 
-            if input.len() < 2 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00001111,
-                operand_count: 1,
-                operands: [
-                    Some(ZOperand::Small {
-                        value: input[1],
-                    }),
-                    None, None, None, None, None, None, None
-                ],
-            
-            })
-        },
-        
-        // short form instruction - 1OP - variable
-        0xa0..=0xaf => { 
-            if input.len() < 2 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: opcode & 0b00001111,
-                operand_count: 1,
-                operands: [
-                    Some(ZOperand::Variable {
-                        value: input[1],
-                    }),
-                    None, None, None, None, None, None, None
-                ],
-            
-            })
-        },
-        
-        // short form instruction - 0OP
-        0xb0..=0xbd | 0xbf => { 
-            Some(ZInstruction{
-                opcode: opcode & 0b00001111,
-                operand_count: 0,
-                operands: [
-                    None, None, None, None, None, None, None, None
-                ],
-            
-            })
-        },
-        
-        // extended form intruction
-        // TODO
-        0xbe => {
-            if input.len() < 2 {
-                return None;
-            }
-            Some(ZInstruction{
-                opcode: input[1],
-                operand_count: 0,
-                operands: [
-                    None, None, None, None, None, None, None, None
-                ],
-            })
-        },
-        
-        // variable form instruction - 2OP - var operand types
-        0xc0..=0xdf => {
-            if input.len() < 2 {
-                return None;
-            }
-            let opcode = input[0] & 0b11111;
-            let optype0 = (input[1] & 0b11110000) | 0b00001111;
-            let optypes = [optype0 , 0b11111111];
-            let (opcount, memsize) = determine_operand_size(optypes);
-            if opcount < 2 {
-                return None;
-            }
-            if input.len() < (memsize + 2) as usize {
-                return None;
-            }
-            let operands = determine_var_operands(
-                optypes,
-                input.clone().split_off(2),
-            );
-            Some(ZInstruction{
-                opcode: opcode,
-                operand_count: opcount,
-                operands: operands
-            })
-        },
-        
-        // variable form instruction - VAR
-        0xe0..=0xff => { 
-            let opcode = input[0] & 0b11111;
-            let mut offset = 0u8;
-            let optypes = match opcode {
-                12 | 26 => {
-                    if input.len() < 3 {
-                        return None
-                    }
-                    offset += 2;
-                    [input[1], input[2]]
-                }
-                _ => {
-                    if input.len() < 2 {
-                        return None
-                    }
-                    offset += 1;
-                    [input[1], 0b11111111]
-                }
-            };
-            let (opcount, memsize) = determine_operand_size(optypes);
-            if input.len() < (memsize + offset + 1) as usize {
-                return None;
-            }
-            let operands = determine_var_operands(
-                optypes,
-                input.clone().split_off(offset as usize + 1),
-            );
-            Some(ZInstruction{
-                opcode: opcode,
-                operand_count: opcount,
-                operands: operands
-            })
-        },
+    // opcodes unknown to `version` are decoded as if they
+    // neither store, branch nor carry text
+    let info = lookup(operand_count, opcode, version);
+
+    let mut store = None;
+    if info.is_some_and(|info| info.store) {
+        store = Some(*memory.get(cursor)?);
+        cursor += 1;
     }
-}*/
+
+    let mut branch = None;
+    if info.is_some_and(|info| info.branch) {
+        let b0 = *memory.get(cursor)?;
+        let offset = if b0 & 0x40 != 0 {
+            cursor += 1;
+            (b0 & 0x3f) as i16
+        } else {
+            let b1 = *memory.get(cursor + 1)?;
+            cursor += 2;
+            // sign extend the 14 bit offset
+            ((((b0 & 0x3f) as u16) << 8 | b1 as u16) << 2) as i16 >> 2
+        };
+        let target = match offset {
+            0 => ZBranchTarget::ReturnFalse,
+            1 => ZBranchTarget::ReturnTrue,
+            _ => ZBranchTarget::Offset(offset),
+        };
+        branch = Some(ZBranch { on_true: b0 & 0x80 != 0, target });
+    }
+
+    let mut text = None;
+    if info.is_some_and(|info| info.text) {
+        let (s, consumed) = codec.decode_at(memory, cursor, abbreviations)?;
+        text = Some(s);
+        cursor += consumed;
+    }
+
+    Some(ZInstruction {
+        opcode,
+        operand_count,
+        operands: operands.operands,
+        store,
+        branch,
+        text,
+        length: cursor - pc,
+    })
+}
 
 /// Decode operands from input vector (which is
 /// supposed to contain the opcode byte and the
 /// operand type bytes). Output a `ZOperands`
 /// structure
 pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
-    if input.len() < 1 {
+    if input.is_empty() {
         return None;
     }
     let opcode = input[0];
@@ -516,17 +474,24 @@ pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
             })
         },
         
-        // extended form instruction
-        // TODO
+        // extended form instruction - EXT - opcode
+        // byte followed by one operand types byte
         0xbe => {
-            if input.len() < 2 {
+            if input.len() < 3 {
+                return None;
+            }
+            let optypes = [input[2], 0b11111111];
+            let (_, memsize) = determine_operand_size(optypes);
+            if input.len() < (memsize + 3) as usize {
                 return None;
             }
+            let operands = determine_var_operands(
+                optypes,
+                input.clone().split_off(3),
+            );
             Some(ZOperands{
-                offset: 1,
-                operands: [
-                    None, None, None, None, None, None, None, None
-                ],
+                offset: 3,
+                operands,
             })
         },
         
@@ -535,9 +500,7 @@ pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
             if input.len() < 2 {
                 return None;
             }
-            let opcode = input[0] & 0b11111;
-            let optype0 = (input[1] & 0b11110000) | 0b00001111;
-            let optypes = [optype0 , 0b11111111];
+            let optypes = [input[1], 0b11111111];
             let (opcount, memsize) = determine_operand_size(optypes);
             if opcount < 2 {
                 return None;
@@ -551,7 +514,7 @@ pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
             );
             Some(ZOperands{
                 offset: 2,
-                operands: operands,
+                operands,
             })
         },
         
@@ -575,7 +538,7 @@ pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
                     [input[1], 0b11111111]
                 }
             };
-            let (opcount, memsize) = determine_operand_size(optypes);
+            let (_, memsize) = determine_operand_size(optypes);
             if input.len() < (memsize + offset) as usize {
                 return None;
             }
@@ -584,19 +547,28 @@ pub fn decode_operands(input: Vec<u8>) -> Option<ZOperands> {
                 input.clone().split_off(offset as usize),
             );
             Some(ZOperands{
-                offset: offset,
-                operands: operands,
+                offset,
+                operands,
             })
         },
     }
 }
 
-fn execute_instruction(instruction: ZInstruction) {
-    // TODO
+/// Execute `instruction`, decoded at the program counter
+/// of `machine`, advancing the program counter past it (or
+/// to wherever the instruction transfers control).
+pub fn execute_instruction<S: Screen>(machine: &mut Machine<S>, instruction: ZInstruction) -> Result<Status, MachineError> {
+    machine.execute(instruction)
 }
 
+#[cfg(test)]
 mod tests {
-    use crate::zcode::{decode_operands, ZInstruction, ZOperand, ZOperands};
+    use crate::util::string_to_zstring;
+    use crate::zcode::{
+        decode_instruction, decode_operands, OperandCount, ZBranch, ZBranchTarget, ZOperand,
+        ZOperands,
+    };
+    use crate::zversion::ZVersion;
 
     #[test]
     fn test_decode_operands_detects_long_form_2op_small_small(){
@@ -774,6 +746,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_variables)]
     fn test_decode_operands_detects_variable_form_2op_var(){
         for i in 0xc0..=0xdf {
             let opcode: u8 = i & 0b11111;
@@ -901,23 +874,30 @@ mod tests {
             ]);
             assert_eq!(decoded, None);
 
-            // provide three large constants - third will be ignored
-            // (even if not enough mem is provided)
+            // provide three large constants (`je` takes up to 4)
             let optypes = 0b00000011;
             let decoded = decode_operands(vec![
                 i, optypes,
-                0x01, 0x02, 0x03, 0x04
+                0x01, 0x02, 0x03, 0x04, 0x05, 0x06
             ]).unwrap();
             let expected = ZOperands {
                 offset: 2,
                 operands: [
                     Some(ZOperand::Large { value: [0x01, 0x02] }),
                     Some(ZOperand::Large{ value: [0x03, 0x04] }),
-                    None, None, None, None, None, None
+                    Some(ZOperand::Large{ value: [0x05, 0x06] }),
+                    None, None, None, None, None
                 ]
             };
             assert_eq!(decoded, expected);
 
+            // provide three large constants - but not enough to read from
+            let decoded = decode_operands(vec![
+                i, optypes,
+                0x01, 0x02, 0x03, 0x04
+            ]);
+            assert_eq!(decoded, None);
+
         }
     }
 
@@ -1071,4 +1051,157 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decode_instruction_store_and_branch() {
+        // add G00 #05 -> sp
+        let memory = vec![0x54, 0x10, 0x05, 0x00];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V3).unwrap();
+        assert_eq!(instruction.opcode(), 20);
+        assert_eq!(instruction.operand_count(), OperandCount::Op2);
+        assert_eq!(instruction.operands()[0], Some(ZOperand::Variable { value: 0x10 }));
+        assert_eq!(instruction.operands()[1], Some(ZOperand::Small { value: 0x05 }));
+        assert_eq!(instruction.store(), Some(0x00));
+        assert_eq!(instruction.branch(), None);
+        assert_eq!(instruction.length(), 4);
+
+        // je L01 #00 ?~rtrue (short branch form)
+        let memory = vec![0xff, 0x41, 0x01, 0x00, 0x41];
+        let instruction = decode_instruction(&memory, 1, ZVersion::V3).unwrap();
+        assert_eq!(instruction.opcode(), 1);
+        assert_eq!(instruction.branch(), Some(ZBranch {
+            on_true: false, target: ZBranchTarget::ReturnTrue
+        }));
+        assert_eq!(instruction.length(), 4);
+
+        // jz L02 ?-3 (long branch form, negative offset)
+        let memory = vec![0xa0, 0x02, 0xbf, 0xfd];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V3).unwrap();
+        assert_eq!(instruction.operand_count(), OperandCount::Op1);
+        assert_eq!(instruction.branch(), Some(ZBranch {
+            on_true: true, target: ZBranchTarget::Offset(-3)
+        }));
+        assert_eq!(instruction.length(), 4);
+
+        // branch byte missing
+        assert_eq!(decode_instruction(&memory[..2], 0, ZVersion::V3), None);
+    }
+
+    #[test]
+    fn test_decode_instruction_je_with_more_than_two_operands() {
+        // je G00 #01 #02 ?+5
+        let memory = vec![0xc1, 0x97, 0x10, 0x01, 0x02, 0xc5];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V3).unwrap();
+        assert_eq!(instruction.operand_count(), OperandCount::Op2);
+        assert_eq!(instruction.operands()[2], Some(ZOperand::Small { value: 0x02 }));
+        assert_eq!(instruction.operands()[3], None);
+        assert_eq!(instruction.branch(), Some(ZBranch {
+            on_true: true, target: ZBranchTarget::Offset(5)
+        }));
+        assert_eq!(instruction.length(), 6);
+
+        // je #1234 #01 #02 #03 ?+5
+        let memory = vec![0xc1, 0x15, 0x12, 0x34, 0x01, 0x02, 0x03, 0xc5];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V3).unwrap();
+        assert_eq!(instruction.operands()[0], Some(ZOperand::Large { value: [0x12, 0x34] }));
+        assert_eq!(instruction.operands()[3], Some(ZOperand::Small { value: 0x03 }));
+        assert_eq!(instruction.branch(), Some(ZBranch {
+            on_true: true, target: ZBranchTarget::Offset(5)
+        }));
+        assert_eq!(instruction.length(), 8);
+    }
+
+    #[test]
+    fn test_decode_instruction_version_dependent_forms() {
+        // call_vs 0x1234 #01 -> L03
+        let memory = vec![0xe0, 0x1f, 0x12, 0x34, 0x01, 0x03];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V5).unwrap();
+        assert_eq!(instruction.operand_count(), OperandCount::Var);
+        assert_eq!(instruction.store(), Some(0x03));
+        assert_eq!(instruction.length(), 6);
+
+        // 1OP:143 is "not" up to version 4 and "call_1n" since version 5
+        let memory = vec![0x8f, 0x12, 0x34, 0x05];
+        assert_eq!(decode_instruction(&memory, 0, ZVersion::V4).unwrap().store(), Some(0x05));
+        assert_eq!(decode_instruction(&memory, 0, ZVersion::V5).unwrap().store(), None);
+        assert_eq!(decode_instruction(&memory, 0, ZVersion::V5).unwrap().length(), 3);
+
+        // save branches in version 3 and stores in version 4
+        let memory = vec![0xb5, 0xc5];
+        assert_eq!(decode_instruction(&memory, 0, ZVersion::V3).unwrap().branch(), Some(ZBranch {
+            on_true: true, target: ZBranchTarget::Offset(5)
+        }));
+        assert_eq!(decode_instruction(&memory, 0, ZVersion::V4).unwrap().store(), Some(0xc5));
+    }
+
+    #[test]
+    fn test_decode_instruction_inline_text() {
+        // print "Hello, world" followed by rtrue
+        let mut memory = vec![0xb2];
+        let text = string_to_zstring("Hello, world".to_string());
+        memory.extend_from_slice(&text);
+        memory.push(0xb0);
+
+        let instruction = decode_instruction(&memory, 0, ZVersion::V3).unwrap();
+        assert_eq!(instruction.operand_count(), OperandCount::Op0);
+        assert_eq!(instruction.opcode(), 2);
+        assert_eq!(instruction.text(), Some("Hello, world"));
+        assert_eq!(instruction.length(), 1 + text.len());
+
+        let rtrue = decode_instruction(&memory, instruction.length(), ZVersion::V3).unwrap();
+        assert_eq!(rtrue.opcode(), 0);
+        assert_eq!(rtrue.text(), None);
+        assert_eq!(rtrue.length(), 1);
+
+        // text without end bit
+        assert_eq!(decode_instruction(&memory[..3], 0, ZVersion::V3), None);
+    }
+
+    #[test]
+    fn test_decode_operands_detects_extended_form() {
+        // opcode and types byte are required
+        assert_eq!(decode_operands(vec![0xbe]), None);
+        assert_eq!(decode_operands(vec![0xbe, 0x09]), None);
+
+        // save_undo - no operands
+        let decoded = decode_operands(vec![0xbe, 0x09, 0xff]).unwrap();
+        let expected = ZOperands {
+            offset: 3,
+            operands: [None, None, None, None, None, None, None, None]
+        };
+        assert_eq!(decoded, expected);
+
+        // set_true_colour - large constant and variable
+        let decoded = decode_operands(vec![0xbe, 0x0d, 0x2f, 0x01, 0x02, 0x03]).unwrap();
+        let expected = ZOperands {
+            offset: 3,
+            operands: [
+                Some(ZOperand::Large { value: [0x01, 0x02] }),
+                Some(ZOperand::Variable { value: 0x03 }),
+                None, None, None, None, None, None
+            ]
+        };
+        assert_eq!(decoded, expected);
+
+        // not enough memory to read the operands
+        assert_eq!(decode_operands(vec![0xbe, 0x0d, 0x2f, 0x01, 0x02]), None);
+    }
+
+    #[test]
+    fn test_decode_instruction_extended_form() {
+        // print_unicode #00e4
+        let memory = vec![0xbe, 0x0b, 0x3f, 0x00, 0xe4];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V5).unwrap();
+        assert_eq!(instruction.operand_count(), OperandCount::Ext);
+        assert_eq!(instruction.opcode(), 11);
+        assert_eq!(instruction.operands()[0], Some(ZOperand::Large { value: [0x00, 0xe4] }));
+        assert_eq!(instruction.store(), None);
+        assert_eq!(instruction.length(), 5);
+
+        // save_undo -> sp
+        let memory = vec![0xbe, 0x09, 0xff, 0x00];
+        let instruction = decode_instruction(&memory, 0, ZVersion::V5).unwrap();
+        assert_eq!(instruction.store(), Some(0x00));
+        assert_eq!(instruction.length(), 4);
+    }
+
 }
\ No newline at end of file
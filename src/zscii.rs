@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 pub const ZSCIITAB: [Option<char>;255] = [
     // null
     Some(' '),
@@ -97,6 +99,15 @@ pub const A2: [char;25] = [
     '#', '\'', '"', '/', '\\', '-', ':', '(', ')'
 ];
 
+/// A2 row of version 1 story files. Unlike later versions
+/// it has no newline (z-char 1 is used for that) but
+/// contains '<' instead.
+pub const A2_V1: [char;25] = [
+    '0', '1', '2', '3', '4', '5', '6', '7',
+    '8', '9', '.', ',', '!', '?', '_', '#',
+    '\'', '"', '/', '\\', '<', '-', ':', '(', ')'
+];
+
 pub const S: [char;69] = [
     'ä', 'ö', 'ü', 'Ä', 'Ö', 'Ü', 'ß', '»',
     '«', 'ë', 'ï', 'ÿ', 'Ë', 'Ï', 'á', 'é',
@@ -107,4 +118,155 @@ pub const S: [char;69] = [
     'ø', 'Ø', 'ã', 'ñ', 'õ', 'Ã', 'Ñ', 'Õ',
     'æ', 'Æ', 'ç', 'Ç', 'þ', 'ð', 'Þ', 'Ð',
     '£', 'œ', 'Œ', '¡', '¿'
-];
\ No newline at end of file
+];
+
+/// Translation between zscii codes and unicode chars. The
+/// codes 155 to 251 are "extra characters" whose meaning is
+/// given by the unicode translation table of a story file
+/// (or the default table if the story does not have one).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZsciiTable {
+    chars: Vec<Option<char>>,
+}
+
+impl Default for ZsciiTable {
+    fn default() -> ZsciiTable {
+        ZsciiTable { chars: ZSCIITAB.to_vec() }
+    }
+}
+
+impl ZsciiTable {
+    /// Table whose extra characters are the (up to 97)
+    /// unicode code points of `entries`, starting at zscii
+    /// code 155. Codes not covered by `entries` are undefined.
+    /// Fails if there are too many entries or an entry is
+    /// not a valid char (e.g. a surrogate).
+    pub fn from_unicode_table(entries: &[u16]) -> Option<ZsciiTable> {
+        if entries.len() > 97 {
+            return None;
+        }
+        let mut chars = ZSCIITAB.to_vec();
+        for (n, c) in chars[155..=251].iter_mut().enumerate() {
+            *c = match entries.get(n) {
+                Some(&e) => Some(char::from_u32(e as u32)?),
+                None => None,
+            };
+        }
+        Some(ZsciiTable { chars })
+    }
+
+    /// Table of the story file in `memory`. Since version 5
+    /// word 3 of the header extension table (whose address is
+    /// found at header offset 0x36) may point to a unicode
+    /// translation table: a byte holding the number of entries
+    /// followed by the entries as words. Stories without one
+    /// use the default table.
+    pub fn from_story(memory: &[u8]) -> Option<ZsciiTable> {
+        let word = |address: usize| -> Option<usize> {
+            Some(((*memory.get(address)? as usize) << 8) | *memory.get(address + 1)? as usize)
+        };
+        if *memory.first()? < 5 {
+            return Some(ZsciiTable::default());
+        }
+        let extension = word(0x36)?;
+        if extension == 0 || word(extension)? < 3 || word(extension + 6)? == 0 {
+            return Some(ZsciiTable::default());
+        }
+        let table = word(extension + 6)?;
+        let mut entries: Vec<u16> = Vec::new();
+        for n in 0..*memory.get(table)? as usize {
+            entries.push(word(table + 1 + 2 * n)? as u16);
+        }
+        ZsciiTable::from_unicode_table(&entries)
+    }
+
+    /// Char of zscii `code` if defined for output.
+    pub fn to_char(&self, code: u16) -> Option<char> {
+        *self.chars.get(code as usize)?
+    }
+
+    /// Zscii code of `c`. Printable ascii and the extra
+    /// characters are preferred over the control codes
+    /// mapping to the same char (like sentence space).
+    pub fn to_zscii(&self, c: char) -> Option<u16> {
+        (32..=126).chain(155..=251).chain(0..self.chars.len())
+            .find(|&code| self.chars.get(code) == Some(&Some(c)))
+            .map(|code| code as u16)
+    }
+
+    /// Zscii code of `c` as typed by the player. Only newline,
+    /// printable ascii and the extra characters are defined
+    /// for input; delete, escape and the cursor, function and
+    /// keypad keys (129-154) are not chars and handled by
+    /// `input::Key`.
+    pub fn input_code(&self, c: char) -> Option<u16> {
+        self.to_zscii(c).filter(|&code| matches!(code, 13 | 32..=126 | 155..=251))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ZsciiTable, S};
+
+    #[test]
+    fn test_zscii_table_defaults() {
+        let table = ZsciiTable::default();
+        for (n, c) in S.iter().enumerate() {
+            assert_eq!(table.to_char(155 + n as u16), Some(*c));
+            assert_eq!(table.to_zscii(*c), Some(155 + n as u16));
+        }
+        assert_eq!(table.to_zscii(' '), Some(32));
+        assert_eq!(table.to_zscii('\n'), Some(13));
+        assert_eq!(table.to_char(224), None);
+        assert_eq!(table.to_char(1000), None);
+    }
+
+    #[test]
+    fn test_zscii_table_from_unicode_table() {
+        // cyrillic small letters a, be, ve
+        let table = ZsciiTable::from_unicode_table(&[0x430, 0x431, 0x432]).unwrap();
+        assert_eq!(table.to_char(155), Some('а'));
+        assert_eq!(table.to_char(157), Some('в'));
+        assert_eq!(table.to_char(158), None);
+        assert_eq!(table.to_zscii('б'), Some(156));
+        assert_eq!(table.to_zscii('ä'), None);
+        assert_eq!(table.to_char(65), Some('A'));
+
+        assert!(ZsciiTable::from_unicode_table(&[0x41; 97]).is_some());
+        assert_eq!(ZsciiTable::from_unicode_table(&[0x41; 98]), None);
+        assert_eq!(ZsciiTable::from_unicode_table(&[0xd800]), None);
+    }
+
+    #[test]
+    fn test_zscii_table_from_story() {
+        let mut memory: Vec<u8> = vec![0; 0x50];
+        memory[0] = 5;
+        memory[0x37] = 0x40;
+        // extension table with 3 words, word 3 -> 0x48
+        memory[0x41] = 3;
+        memory[0x47] = 0x48;
+        // unicode table with 2 entries
+        memory[0x48..0x4d].copy_from_slice(&[2, 0x04, 0x30, 0x04, 0x31]);
+
+        let table = ZsciiTable::from_story(&memory).unwrap();
+        assert_eq!(table.to_char(155), Some('а'));
+        assert_eq!(table.to_char(156), Some('б'));
+        assert_eq!(table.to_char(157), None);
+
+        // no unicode table in version 3
+        memory[0] = 3;
+        assert_eq!(ZsciiTable::from_story(&memory), Some(ZsciiTable::default()));
+    }
+
+    #[test]
+    fn test_zscii_table_input_codes() {
+        let table = ZsciiTable::default();
+        assert_eq!(table.input_code('a'), Some(97));
+        assert_eq!(table.input_code(' '), Some(32));
+        assert_eq!(table.input_code('\n'), Some(13));
+        assert_eq!(table.input_code('ä'), Some(155));
+        assert_eq!(table.input_code('\t'), None);
+        assert_eq!(table.input_code('\u{8}'), None);
+        assert_eq!(table.input_code('€'), None);
+    }
+}
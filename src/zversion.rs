@@ -0,0 +1,51 @@
+/// Version of the z-machine a story file is written for.
+/// Text encoding, instruction set and memory layout all
+/// differ slightly between versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ZVersion {
+    V1,
+    V2,
+    V3,
+    V4,
+    V5,
+    V6,
+    V7,
+    V8,
+}
+
+impl ZVersion {
+    /// Map the version number found in the first byte
+    /// of a story file to a `ZVersion`.
+    pub fn from_u8(n: u8) -> Option<ZVersion> {
+        match n {
+            1 => Some(ZVersion::V1),
+            2 => Some(ZVersion::V2),
+            3 => Some(ZVersion::V3),
+            4 => Some(ZVersion::V4),
+            5 => Some(ZVersion::V5),
+            6 => Some(ZVersion::V6),
+            7 => Some(ZVersion::V7),
+            8 => Some(ZVersion::V8),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self) -> u8 {
+        *self as u8 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZVersion;
+
+    #[test]
+    fn test_zversion_numbers_roundtrip() {
+        for n in 1..=8 {
+            assert_eq!(ZVersion::from_u8(n).unwrap().number(), n);
+        }
+        assert_eq!(ZVersion::from_u8(0), None);
+        assert_eq!(ZVersion::from_u8(9), None);
+        assert!(ZVersion::V1 < ZVersion::V3);
+    }
+}